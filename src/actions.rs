@@ -1,28 +1,131 @@
-use std::time::Duration;
+use std::{
+    fmt,
+    sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}},
+    time::Duration,
+};
 use eyre::Result;
+use tokio::io::AsyncReadExt;
 use tokio::process::Command;
 
-use crate::config::{IdleAction, IdleActionKind};
+use crate::config::{IdleAction, IdleActionKind, SequenceStep, SuspendMethod};
 use crate::log::log_message;
 
+/// Set by `--dry-run`: commands are logged instead of executed
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_dry_run(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Stderr kept per command, older output is dropped
+const MAX_CAPTURED_STDERR: usize = 64 * 1024;
+
+/// Lines of stderr included when a command fails, from `idle.command_log_lines`
+static COMMAND_LOG_LINES: AtomicUsize = AtomicUsize::new(5);
+
+pub fn set_command_log_lines(lines: usize) {
+    COMMAND_LOG_LINES.store(lines, Ordering::Relaxed);
+}
+
+fn command_log_lines() -> usize {
+    COMMAND_LOG_LINES.load(Ordering::Relaxed)
+}
+
+/// Default command timeout in seconds, from `idle.command_timeout_seconds`
+static COMMAND_TIMEOUT: AtomicU64 = AtomicU64::new(30);
+
+pub fn set_command_timeout(seconds: u64) {
+    COMMAND_TIMEOUT.store(seconds, Ordering::Relaxed);
+}
+
+fn command_timeout() -> Duration {
+    Duration::from_secs(COMMAND_TIMEOUT.load(Ordering::Relaxed))
+}
+
+/// `idle.suspend_method`, deciding whether suspend actions go through logind
+static SUSPEND_METHOD: Mutex<SuspendMethod> = Mutex::new(SuspendMethod::Command);
+
+pub fn set_suspend_method(method: SuspendMethod) {
+    *SUSPEND_METHOD.lock().unwrap() = method;
+}
+
+fn suspend_method() -> SuspendMethod {
+    *SUSPEND_METHOD.lock().unwrap()
+}
+
+/// Log a command in place of running it, returns true in dry-run mode
+pub fn dry_run_skip(cmd: &str) -> bool {
+    if is_dry_run() {
+        log_message(&format!("DRY-RUN: would run '{}'", cmd));
+    }
+    is_dry_run()
+}
+
+/// Environment handed to every action child, as shown by `stasis debug-env`
+pub fn action_env() -> Vec<(String, String)> {
+    let mut vars: Vec<_> = std::env::vars().collect();
+    vars.sort();
+    vars
+}
+
+/// How an action's command is executed
+#[derive(Debug, Clone)]
+pub enum ActionCommand {
+    /// Interpreted by `sh -c`
+    Shell(String),
+    /// Executed directly, no shell
+    Exec(Vec<String>),
+}
+
+impl fmt::Display for ActionCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionCommand::Shell(cmd) => write!(f, "{}", cmd),
+            ActionCommand::Exec(argv) => write!(f, "{}", argv.join(" ")),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ActionRequest {
-    RunCommand(String),
+    RunCommand(ActionCommand),
     PreSuspend,
+    Dim { target_percent: u32, duration: Duration },
+    Sequence(Vec<SequenceStep>),
+    /// Power outputs off via wlr-output-power-management
+    #[cfg(feature = "wlr_output_power")]
+    NativeDpms,
+    /// Call the logind sleep method instead of running a command
+    LogindSleep(SuspendMethod),
     #[allow(dead_code)]
     Skip(String),
 }
 
+/// The action's command, run directly when it was given as an argv list
+pub fn action_command(action: &IdleAction) -> ActionCommand {
+    match &action.argv {
+        Some(argv) => ActionCommand::Exec(argv.clone()),
+        None => ActionCommand::Shell(action.command.clone()),
+    }
+}
+
 pub async fn prepare_action(action: &IdleAction) -> Vec<ActionRequest> {
     let cmd = action.command.clone();
     let kind = action.kind.clone();
+    let command = action_command(action);
 
     match kind {
         IdleActionKind::Suspend => {
             let mut reqs = Vec::new();
             reqs.push(ActionRequest::PreSuspend);
-            if !cmd.trim().is_empty() {
-                reqs.push(ActionRequest::RunCommand(cmd));
+            match suspend_method() {
+                SuspendMethod::Command if !cmd.trim().is_empty() => reqs.push(ActionRequest::RunCommand(command)),
+                SuspendMethod::Command => {}
+                method => reqs.push(ActionRequest::LogindSleep(method)),
             }
             reqs
         }
@@ -33,45 +136,159 @@ pub async fn prepare_action(action: &IdleAction) -> Vec<ActionRequest> {
                 log_message("Lockscreen already running, skipping action.");
                 vec![ActionRequest::Skip(cmd)]
             } else {
-                vec![ActionRequest::RunCommand(cmd)]
+                vec![ActionRequest::RunCommand(command)]
             }
         }
 
+        IdleActionKind::Sequence(steps) => vec![ActionRequest::Sequence(steps)],
+
+        #[cfg(feature = "wlr_output_power")]
+        IdleActionKind::Dpms if cmd.trim().is_empty() => vec![ActionRequest::NativeDpms],
+
+        IdleActionKind::Brightness if action.dim_to.is_some() => {
+            vec![ActionRequest::Dim {
+                target_percent: action.dim_to.unwrap_or(100),
+                duration: Duration::from_secs(action.fade_seconds),
+            }]
+        }
+
         _ => {
             // Default: run the configured command if any.
             if cmd.trim().is_empty() {
                 vec![]
             } else {
-                vec![ActionRequest::RunCommand(cmd)]
+                vec![ActionRequest::RunCommand(command)]
             }
         }
     }
 }
 
+/// How a command may be interrupted. The default only stops waiting once
+/// the timeout passes, killing is opt-in per call site.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunLimits {
+    /// Kill the child if the task running it is aborted (reversible actions)
+    pub kill_on_drop: bool,
+    /// Longest the command may run, None for `idle.command_timeout_seconds`
+    pub timeout: Option<Duration>,
+    /// Kill the command when it overruns; otherwise it is left running and no longer waited on
+    pub kill_on_timeout: bool,
+}
+
 /// Run a shell command, redirecting stdout/stderr to a small log file.
 pub async fn run_command_silent(cmd: &str) -> Result<()> {
-    let log_file = "/tmp/stasis.log";
-    let fut = async {
-        let mut child = Command::new("sh")
-            .arg("-c")
-            .arg(format!("{cmd} >> {log_file} 2>&1"))
-            .envs(std::env::vars())
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()?;
-
-        let status = child.wait().await?;
-        if !status.success() {
-            eyre::bail!("Command '{}' exited with status {:?}", cmd, status.code());
+    run_shell(cmd, RunLimits::default()).await
+}
+
+async fn run_shell(cmd: &str, limits: RunLimits) -> Result<()> {
+    if dry_run_skip(cmd) {
+        return Ok(());
+    }
+
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(cmd);
+    run_logged(command, cmd, limits).await
+}
+
+/// Run an action command, either through the shell or directly as argv
+pub async fn run_action_command(command: &ActionCommand, limits: RunLimits) -> Result<()> {
+    match command {
+        ActionCommand::Shell(cmd) => run_shell(cmd, limits).await,
+        ActionCommand::Exec(argv) => run_exec_silent(argv, limits).await,
+    }
+}
+
+/// Execute argv without a shell, appending output to the same log file
+pub async fn run_exec_silent(argv: &[String], limits: RunLimits) -> Result<()> {
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| eyre::eyre!("Empty command"))?;
+    if dry_run_skip(&argv.join(" ")) {
+        return Ok(());
+    }
+
+    let mut command = Command::new(program);
+    command.args(args);
+    run_logged(command, &argv.join(" "), limits).await
+}
+
+/// Run a prepared command with stdout appended to the Stasis log. Stderr is
+/// captured as well so a failure reports the exit code and its last lines.
+async fn run_logged(mut command: Command, display: &str, limits: RunLimits) -> Result<()> {
+    let timeout = limits.timeout.unwrap_or_else(command_timeout);
+    let log = crate::log::open_log_file()?;
+    let mut child = command
+        .envs(action_env())
+        .stdin(std::process::Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(limits.kill_on_drop)
+        .spawn()?;
+
+    // Read stderr alongside the child, a daemonizing command (swaylock -f)
+    // can leave the pipe open long after the command itself has exited
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let mut reader = tokio::spawn({
+        let captured = Arc::clone(&captured);
+        let stderr = child.stderr.take();
+        async move {
+            let Some(mut stderr) = stderr else { return };
+            let mut chunk = [0u8; 4096];
+            while let Ok(n) = stderr.read(&mut chunk).await && n > 0 {
+                if let Ok(mut buf) = captured.lock() {
+                    buf.extend_from_slice(&chunk[..n]);
+                    let excess = buf.len().saturating_sub(MAX_CAPTURED_STDERR);
+                    buf.drain(..excess);
+                }
+            }
+        }
+    });
+
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status,
+        Err(_) if limits.kill_on_timeout => {
+            let _ = child.kill().await;
+            log_message(&format!("Command '{}' killed after exceeding its {}s timeout", display, timeout.as_secs()));
+            reader.abort();
+            eyre::bail!("Command '{}' timed out after {}s", display, timeout.as_secs());
+        }
+        Err(_) => {
+            log_message(&format!(
+                "Command '{}' still running after {}s, no longer waiting on it",
+                display,
+                timeout.as_secs()
+            ));
+            reader.abort();
+            return Ok(());
         }
-        Ok::<(), eyre::Report>(())
     };
+    if tokio::time::timeout(Duration::from_millis(200), &mut reader).await.is_err() {
+        reader.abort();
+    }
+    let stderr = captured.lock().map(|buf| buf.clone()).unwrap_or_default();
+    std::io::Write::write_all(&mut &log, &stderr)?;
 
-    tokio::time::timeout(Duration::from_secs(30), fut).await??;
+    let status = status?;
+    if !status.success() {
+        let status = match status.code() {
+            Some(code) => format!("exit code {}", code),
+            None => "a signal".to_string(),
+        };
+        let stderr = String::from_utf8_lossy(&stderr);
+        let tail = last_lines(&stderr, command_log_lines());
+        if tail.is_empty() {
+            eyre::bail!("Command '{}' failed with {}", display, status);
+        }
+        eyre::bail!("Command '{}' failed with {}: {}", display, status, tail);
+    }
     Ok(())
 }
 
+/// The last `n` non-empty lines of `text`, joined with " | " for a one-line log entry
+fn last_lines(text: &str, n: usize) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    lines[lines.len().saturating_sub(n)..].join(" | ")
+}
 
 pub async fn is_process_running(cmd: &str) -> bool {
     if cmd.trim().is_empty() {
@@ -88,3 +305,26 @@ pub async fn is_process_running(cmd: &str) -> bool {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_opted_in_commands_are_killed_on_timeout() {
+        let dir = std::env::temp_dir();
+        let waited = dir.join(format!("stasis-wait-only-{}", std::process::id()));
+        let killed = dir.join(format!("stasis-killed-{}", std::process::id()));
+        let command = |marker: &std::path::Path| ActionCommand::Shell(format!("sleep 0.5; touch {}", marker.display()));
+        let timeout = Some(Duration::from_millis(100));
+
+        let wait_only = RunLimits { timeout, ..RunLimits::default() };
+        assert!(run_action_command(&command(&waited), wait_only).await.is_ok());
+        let kill = RunLimits { timeout, kill_on_timeout: true, ..RunLimits::default() };
+        assert!(run_action_command(&command(&killed), kill).await.is_err());
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(waited.exists());
+        assert!(!killed.exists());
+        let _ = std::fs::remove_file(&waited);
+    }
+}
@@ -5,7 +5,16 @@ use serde_json::Value;
 use sysinfo::{System, RefreshKind, ProcessRefreshKind, ProcessesToUpdate};
 
 use crate::config::IdleConfig;
-use crate::log::log_message;
+use crate::error::InhibitError;
+use crate::idle_timer::InhibitSource;
+use crate::log::{log_debug_message, log_message};
+use crate::wayland::toplevels::ToplevelApps;
+
+/// A window reported by the compositor
+struct Window {
+    app_id: String,
+    fullscreen: bool,
+}
 
 /// Tracks currently running apps to inhibit idle
 pub struct AppInhibitor {
@@ -14,6 +23,9 @@ pub struct AppInhibitor {
     active_apps: HashSet<String>,
     desktop: String,
     checks_since_reset: u32,
+    /// Open windows from wlr-foreign-toplevel-management, used when there is no compositor CLI
+    toplevel_apps: Option<ToplevelApps>,
+    fullscreen_inhibited: bool,
     #[allow(dead_code)]
     idle_timer: Arc<Mutex<crate::idle_timer::IdleTimer>>,
 }
@@ -37,40 +49,92 @@ impl AppInhibitor {
             active_apps: HashSet::new(),
             desktop,
             checks_since_reset: 0,
+            toplevel_apps: None,
+            fullscreen_inhibited: false,
             idle_timer,
         }
     }
 
-    /// Returns true if any app in inhibit_apps is currently running
+    pub fn set_toplevel_apps(&mut self, apps: ToplevelApps) {
+        log_message("Using wlr-foreign-toplevel-management for app inhibition fallback");
+        self.toplevel_apps = Some(apps);
+    }
+
+    /// Returns true if any app in inhibit_apps is currently running, or
+    /// with `inhibit_on_fullscreen` if any window is fullscreen
     pub async fn is_any_app_running(&mut self) -> bool {
         let mut new_active_apps = HashSet::new();
 
-        let running = match self.check_compositor_windows().await {
-            Ok(result_apps) => {
-                new_active_apps = result_apps;
-                !new_active_apps.is_empty()
-            },
-            Err(_) => self.check_processes_with_tracking(&mut new_active_apps),
+        let windows = match self.check_compositor_windows().await {
+            Ok(windows) => Some(windows),
+            Err(e) => {
+                if !matches!(e, InhibitError::Unsupported(_)) {
+                    log_debug_message(&format!("Compositor IPC failed, falling back: {}", e));
+                }
+                self.toplevel_apps.as_ref().map(|toplevels| {
+                    toplevels
+                        .windows()
+                        .into_iter()
+                        .map(|(app_id, fullscreen)| Window { app_id, fullscreen })
+                        .collect()
+                })
+            }
         };
 
+        let fullscreen = match windows {
+            Some(windows) => {
+                new_active_apps = windows
+                    .iter()
+                    .filter(|w| !w.app_id.is_empty() && self.should_inhibit_for_app(&w.app_id))
+                    .map(|w| w.app_id.clone())
+                    .collect();
+                windows.iter().any(|w| w.fullscreen)
+            }
+            None => {
+                self.check_processes_with_tracking(&mut new_active_apps);
+                false
+            }
+        };
+
+        self.update(new_active_apps, fullscreen)
+    }
+
+    /// Record the matched apps and whether a window is fullscreen, returning
+    /// whether either inhibits idle
+    fn update(&mut self, new_active_apps: HashSet<String>, fullscreen: bool) -> bool {
         for app in &new_active_apps {
             if !self.active_apps.contains(app) {
                 log_message(&format!("App inhibit active: {}", app));
             }
         }
 
+        let fullscreen_inhibited = self.cfg.inhibit_on_fullscreen && fullscreen;
+        if fullscreen_inhibited != self.fullscreen_inhibited {
+            log_message(if fullscreen_inhibited {
+                "Fullscreen window detected, inhibiting idle"
+            } else {
+                "No fullscreen window, fullscreen inhibit cleared"
+            });
+        }
+        self.fullscreen_inhibited = fullscreen_inhibited;
+
         self.active_apps = new_active_apps;
-        running
+        !self.active_apps.is_empty() || fullscreen_inhibited
+    }
+
+    /// True while a fullscreen window is inhibiting idle
+    pub fn is_fullscreen_inhibited(&self) -> bool {
+        self.fullscreen_inhibited
     }
 
     /// Process-based fallback - only refresh what we need
     fn check_processes_with_tracking(&mut self, new_active_apps: &mut HashSet<String>) -> bool {
-        const RESET_THRESHOLD: u32 = 150; // Approx 10 mins (150 checks * 4s/check)
+        const RESET_THRESHOLD: u32 = 150; // Approx 10 mins at the default 4s poll interval
 
         self.checks_since_reset += 1;
 
         if self.checks_since_reset >= RESET_THRESHOLD {
-            log_message("Periodic process tracker reset to reclaim memory.");
+            log_debug_message("Periodic process tracker reset to reclaim memory.");
             self.system = System::new_with_specifics(
                 RefreshKind::nothing().with_processes(ProcessRefreshKind::nothing())
             );
@@ -113,51 +177,76 @@ impl AppInhibitor {
     }
 
     /// Check compositor windows via IPC
-    async fn check_compositor_windows(&self) -> Result<HashSet<String>, Box<dyn std::error::Error + Send + Sync>> {
+    async fn check_compositor_windows(&self) -> Result<Vec<Window>, InhibitError> {
         match self.desktop.as_str() {
-            "niri" => {
-                let app_ids = self.try_niri_ipc().await?;
-                Ok(app_ids.into_iter()
-                    .filter(|app| self.should_inhibit_for_app(app))
-                    .collect())
-            }
-            "hyprland" => {
-                let windows = self.try_hyprland_ipc().await?;
-                Ok(windows.into_iter()
-                    .filter_map(|win| win.get("app_id").and_then(|v| v.as_str()).map(|s| s.to_string()))
-                    .filter(|app| self.should_inhibit_for_app(app))
-                    .collect())
-            }
-            _ => Err("No IPC available, fallback to process scan".into())
+            "niri" => self.try_niri_ipc().await,
+            "hyprland" => self.try_hyprland_ipc().await,
+            "sway" => self.try_sway_ipc().await,
+            other => Err(InhibitError::Unsupported(other.to_string()))
         }
     }
 
-    async fn try_niri_ipc(&self) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
-        let output = Command::new("niri").args(&["msg", "windows"]).output().await?;
+    async fn try_niri_ipc(&self) -> Result<Vec<Window>, InhibitError> {
+        let output = Command::new("niri").args(["msg", "-j", "windows"]).output().await
+            .map_err(|source| InhibitError::Spawn { command: "niri", source })?;
         if !output.status.success() {
-            return Err(format!("niri command failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+            return Err(InhibitError::CommandFailed {
+                command: "niri",
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
         }
-        let text = String::from_utf8(output.stdout)?;
-        Ok(text.lines()
-            .filter_map(|line| line.strip_prefix("  App ID: "))
-            .map(|s| s.trim_matches('"').to_string())
+
+        let windows: Vec<Value> = serde_json::from_slice(&output.stdout)
+            .map_err(|e| InhibitError::MalformedOutput { command: "niri", reason: e.to_string() })?;
+        Ok(windows.iter()
+            .map(|win| Window {
+                app_id: win.get("app_id").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                fullscreen: ["is_fullscreen", "fullscreen"]
+                    .iter()
+                    .any(|key| win.get(key).and_then(|v| v.as_bool()).unwrap_or(false)),
+            })
             .collect())
     }
 
-    async fn try_hyprland_ipc(&self) -> Result<Vec<Value>, Box<dyn std::error::Error + Send + Sync>> {
-        let output = Command::new("hyprctl").args(&["clients", "-j"]).output().await?;
+    async fn try_hyprland_ipc(&self) -> Result<Vec<Window>, InhibitError> {
+        let output = Command::new("hyprctl").args(["clients", "-j"]).output().await
+            .map_err(|source| InhibitError::Spawn { command: "hyprctl", source })?;
         if !output.status.success() {
-            return Err(format!("hyprctl command failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+            return Err(InhibitError::CommandFailed {
+                command: "hyprctl",
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
         }
 
-        let clients: Vec<Value> = serde_json::from_slice(&output.stdout)?;
-        let windows = clients.into_iter().map(|mut client| {
-            if let Some(class) = client.get("class").cloned() {
-                client.as_object_mut().unwrap().insert("app_id".to_string(), class);
-            }
-            client
-        }).collect();
+        let clients: Vec<Value> = serde_json::from_slice(&output.stdout)
+            .map_err(|e| InhibitError::MalformedOutput { command: "hyprctl", reason: e.to_string() })?;
+        Ok(clients.iter()
+            .map(|client| Window {
+                app_id: client.get("class").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                // Older Hyprland reports a bool, newer a mode where 1 is only maximized
+                fullscreen: match client.get("fullscreen") {
+                    Some(Value::Bool(b)) => *b,
+                    Some(Value::Number(n)) => n.as_u64().unwrap_or(0) >= 2,
+                    _ => false,
+                },
+            })
+            .collect())
+    }
 
+    async fn try_sway_ipc(&self) -> Result<Vec<Window>, InhibitError> {
+        let output = Command::new("swaymsg").args(["-t", "get_tree"]).output().await
+            .map_err(|source| InhibitError::Spawn { command: "swaymsg", source })?;
+        if !output.status.success() {
+            return Err(InhibitError::CommandFailed {
+                command: "swaymsg",
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+
+        let tree: Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| InhibitError::MalformedOutput { command: "swaymsg", reason: e.to_string() })?;
+        let mut windows = Vec::new();
+        collect_sway_windows(&tree, &mut windows);
         Ok(windows)
     }
 
@@ -191,28 +280,46 @@ impl AppInhibitor {
     }
 }
 
+/// Walk a sway `get_tree` node, collecting `app_id` for Wayland windows and
+/// `window_properties.class` for XWayland ones
+fn collect_sway_windows(node: &Value, out: &mut Vec<Window>) {
+    let app_id = node
+        .get("app_id")
+        .and_then(|v| v.as_str())
+        .or_else(|| node.pointer("/window_properties/class").and_then(|v| v.as_str()));
+    if let Some(app_id) = app_id {
+        out.push(Window {
+            app_id: app_id.to_string(),
+            fullscreen: node.get("fullscreen_mode").and_then(|v| v.as_u64()).unwrap_or(0) > 0,
+        });
+    }
+
+    for key in ["nodes", "floating_nodes"] {
+        if let Some(children) = node.get(key).and_then(|v| v.as_array()) {
+            for child in children {
+                collect_sway_windows(child, out);
+            }
+        }
+    }
+}
+
 pub fn spawn_app_inhibit_task(
     idle_timer: Arc<Mutex<crate::idle_timer::IdleTimer>>,
     cfg: Arc<IdleConfig>
 ) -> Arc<Mutex<AppInhibitor>> {
+    let poll_interval = std::time::Duration::from_secs(cfg.app_poll_seconds);
+    log_message(&format!("App inhibitor polling every {}s", cfg.app_poll_seconds));
     let inhibitor = Arc::new(Mutex::new(AppInhibitor::new(cfg, Arc::clone(&idle_timer))));
- 
+
     let inhibitor_clone = Arc::clone(&inhibitor);
     tokio::spawn(async move {
         loop {
             {
-                let mut guard = inhibitor_clone.lock().await;
-                let was_running = !guard.active_apps.is_empty();
-                let any_running = guard.is_any_app_running().await;
-
-                let mut timer = idle_timer.lock().await;
-                if any_running && !was_running {
-                    timer.pause(false);
-                } else if !any_running && was_running {
-                    timer.resume(false);
-                }
+                // Covers fullscreen windows too, the flag only changes on a transition
+                let inhibiting = inhibitor_clone.lock().await.is_any_app_running().await;
+                idle_timer.lock().await.set_inhibited(InhibitSource::Apps, inhibiting);
             }
-            tokio::time::sleep(std::time::Duration::from_secs(4)).await;
+            tokio::time::sleep(poll_interval).await;
         }
     });
 
@@ -220,3 +327,70 @@ pub fn spawn_app_inhibit_task(
     inhibitor
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idle_timer::IdleTimer;
+
+    #[tokio::test]
+    async fn fullscreen_window_closing_resumes_idle() {
+        let source = "idle:\n  force_desktop true\n  inhibit_on_fullscreen true\nend\n";
+        let cfg = Arc::new(crate::config::load_config_str(source).unwrap());
+        let timer = Arc::new(Mutex::new(IdleTimer::new(&cfg)));
+        let mut inhibitor = AppInhibitor::new(Arc::clone(&cfg), Arc::clone(&timer));
+
+        let inhibiting = inhibitor.update(HashSet::new(), true);
+        assert!(inhibiting);
+        assert!(inhibitor.is_fullscreen_inhibited());
+        timer.lock().await.set_inhibited(InhibitSource::Apps, inhibiting);
+        assert!(timer.lock().await.auto_inhibited());
+
+        let inhibiting = inhibitor.update(HashSet::new(), false);
+        assert!(!inhibiting);
+        assert!(!inhibitor.is_fullscreen_inhibited());
+        timer.lock().await.set_inhibited(InhibitSource::Apps, inhibiting);
+        assert!(!timer.lock().await.auto_inhibited());
+    }
+
+    /// Trimmed `swaymsg -t get_tree` output: a workspace with a tiled
+    /// fullscreen Wayland window and a floating XWayland one
+    const SWAY_TREE: &str = r#"{
+  "type": "root",
+  "nodes": [{
+    "type": "output",
+    "name": "eDP-1",
+    "nodes": [{
+      "type": "workspace",
+      "name": "1",
+      "nodes": [{
+        "type": "con",
+        "app_id": "mpv",
+        "fullscreen_mode": 1,
+        "nodes": [],
+        "floating_nodes": []
+      }],
+      "floating_nodes": [{
+        "type": "floating_con",
+        "app_id": null,
+        "window_properties": { "class": "Steam", "instance": "steam" },
+        "fullscreen_mode": 0,
+        "nodes": [],
+        "floating_nodes": []
+      }]
+    }],
+    "floating_nodes": []
+  }],
+  "floating_nodes": []
+}"#;
+
+    #[test]
+    fn sway_tree_yields_wayland_and_xwayland_windows() {
+        let tree: Value = serde_json::from_str(SWAY_TREE).unwrap();
+        let mut windows = Vec::new();
+        collect_sway_windows(&tree, &mut windows);
+
+        let found: Vec<_> = windows.iter().map(|w| (w.app_id.as_str(), w.fullscreen)).collect();
+        assert_eq!(found, [("mpv", true), ("Steam", false)]);
+    }
+}
@@ -1,29 +1,153 @@
 use std::fs;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
 
-use crate::log::{log_error_message, log_message}; // assuming you have this
+use crate::log::{log_error_message, log_message, log_warn_message}; // assuming you have this
 
-/// Represents brightness state as absolute value (not percent)
+/// Number of intermediate writes used for a fade
+pub const DEFAULT_FADE_STEPS: u32 = 20;
+
+/// Bumped whenever a fade starts or brightness is set directly, so that
+/// an older fade still in flight stops writing
+static FADE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Cleared by `probe_backlight_access` when the sysfs `brightness` file can't be written
+static BACKLIGHT_WRITABLE: AtomicBool = AtomicBool::new(true);
+
+/// Brightness captured before dimming, as the raw sysfs value and as percent of max
 #[derive(Clone, Debug)]
 pub struct BrightnessState {
     pub value: u32,
+    /// None when `max_brightness` couldn't be read at capture
+    pub percent: Option<u32>,
     pub device: String,
+    /// Fade back over this duration on restore instead of jumping
+    pub fade: Option<Duration>,
+    /// Run instead of the sysfs write when the backlight isn't writable, with
+    /// `{value}` and `{percent}` replaced by the captured brightness
+    pub restore_command: Option<String>,
 }
 
-pub fn capture_brightness() -> Option<BrightnessState> {
-    let base = Path::new("/sys/class/backlight");
+/// Where backlight devices live, the read helpers take it as `base`
+const BACKLIGHT: &str = "/sys/class/backlight";
+
+fn first_device(base: &Path) -> Option<String> {
     let device = fs::read_dir(base).ok()?.next()?.ok()?.file_name();
-    let device = device.to_string_lossy().to_string();
+    Some(device.to_string_lossy().to_string())
+}
+
+fn read_value(base: &Path, device: &str, file: &str) -> Option<u32> {
+    let path = base.join(device).join(file);
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn max_brightness(base: &Path, device: &str) -> Option<u32> {
+    read_value(base, device, "max_brightness").filter(|&max| max > 0)
+}
+
+/// Raw value as a rounded percentage of `max`
+pub fn to_percent(value: u32, max: u32) -> u32 {
+    ((value as u64 * 100 + max as u64 / 2) / max.max(1) as u64).min(100) as u32
+}
+
+/// Percentage of `max` as a raw value
+pub fn from_percent(percent: u32, max: u32) -> u32 {
+    (max as u64 * percent.min(100) as u64 / 100) as u32
+}
+
+pub fn capture_brightness() -> Option<BrightnessState> {
+    capture_from(Path::new(BACKLIGHT))
+}
 
-    let current = fs::read_to_string(base.join(&device).join("brightness")).ok()?;
+fn capture_from(base: &Path) -> Option<BrightnessState> {
+    let device = first_device(base)?;
+    let value = read_value(base, &device, "brightness")?;
 
     Some(BrightnessState {
-        value: current.trim().parse().ok()?,
+        value,
+        percent: max_brightness(base, &device).map(|max| to_percent(value, max)),
         device,
+        fade: None,
+        restore_command: None,
     })
 }
 
+/// Check once at startup that the backlight can be written, so a missing
+/// udev rule shows up in the log instead of as brightness that never comes back
+pub fn probe_backlight_access() {
+    let Some(device) = first_device(Path::new(BACKLIGHT)) else {
+        return;
+    };
+    let path = Path::new(BACKLIGHT).join(&device).join("brightness");
+
+    // Opening for write checks permissions without changing anything
+    if let Err(e) = fs::OpenOptions::new().write(true).open(&path) {
+        BACKLIGHT_WRITABLE.store(false, Ordering::SeqCst);
+        log_warn_message(&format!(
+            "Backlight {} is not writable ({}), brightness can't be restored natively. \
+            Add a udev rule such as \
+            ACTION==\"add\", SUBSYSTEM==\"backlight\", RUN+=\"/bin/chgrp video $sys$devpath/brightness\", RUN+=\"/bin/chmod g+w $sys$devpath/brightness\" \
+            and join the video group, or set restore_command on the brightness action.",
+            path.display(), e
+        ));
+    }
+}
+
+/// Fit a captured value to the device's current `max_brightness`, which can
+/// change under driver quirks; the kernel rejects larger writes. The captured
+/// percentage is rescaled when known, otherwise the value is clamped.
+fn clamp_to_max(state: &BrightnessState) -> u32 {
+    let Some(max) = max_brightness(Path::new(BACKLIGHT), &state.device) else {
+        return state.value;
+    };
+    if state.value > max {
+        let value = state.percent.map_or(max, |percent| from_percent(percent, max));
+        log_message(&format!(
+            "Captured brightness {} exceeds max_brightness {} for device {}, restoring {}",
+            state.value, max, state.device, value
+        ));
+        return value;
+    }
+    state.value
+}
+
 pub fn restore_brightness(state: &BrightnessState) {
+    let state = &BrightnessState { value: clamp_to_max(state), ..state.clone() };
+
+    if !BACKLIGHT_WRITABLE.load(Ordering::SeqCst)
+        && let Some(cmd) = &state.restore_command
+    {
+        // A fade can't run either, stop any in flight and hand over to the command
+        FADE_GENERATION.fetch_add(1, Ordering::SeqCst);
+        let percent = state.percent.map(|p| p.to_string()).unwrap_or_default();
+        let cmd = cmd.replace("{value}", &state.value.to_string()).replace("{percent}", &percent);
+        tokio::spawn(async move {
+            match crate::actions::run_command_silent(&cmd).await {
+                Ok(()) => log_message(&format!("Brightness restored with restore_command: {}", cmd)),
+                Err(e) => log_error_message(&format!("Brightness restore_command failed: {}", e)),
+            }
+        });
+        return;
+    }
+
+    if let Some(duration) = state.fade {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let Some(current) = read_value(Path::new(BACKLIGHT), &state.device, "brightness") else {
+                log_error_message(&format!("Could not read brightness for device {}", state.device));
+                return;
+            };
+            if fade(&state.device, current, state.value, duration, DEFAULT_FADE_STEPS).await {
+                log_message(&format!("Brightness faded back to {} for device {}", state.value, state.device));
+            }
+        });
+        return;
+    }
+
+    // Cancel any fade still running before jumping back
+    FADE_GENERATION.fetch_add(1, Ordering::SeqCst);
+
     let path = format!("/sys/class/backlight/{}/brightness", state.device);
     if let Err(e) = fs::write(&path, state.value.to_string()) {
         log_error_message(&format!(
@@ -36,3 +160,82 @@ pub fn restore_brightness(state: &BrightnessState) {
     }
 }
 
+/// Fade from the current brightness to `target_percent` of max over `duration`
+pub async fn dim_to(target_percent: u32, duration: Duration, steps: u32) {
+    let base = Path::new(BACKLIGHT);
+    let Some(device) = first_device(base) else {
+        log_error_message("No backlight device found for dimming");
+        return;
+    };
+
+    let (Some(current), Some(max)) = (read_value(base, &device, "brightness"), max_brightness(base, &device)) else {
+        log_error_message(&format!("Could not read brightness for device {}", device));
+        return;
+    };
+
+    let target = from_percent(target_percent, max);
+    if target >= current {
+        return; // Already at or below target
+    }
+
+    if fade(&device, current, target, duration, steps).await {
+        log_message(&format!("Brightness dimmed to {}% for device {}", target_percent, device));
+    }
+}
+
+/// Write intermediate values from `from` to `to`. Returns false if superseded or failed.
+async fn fade(device: &str, from: u32, to: u32, duration: Duration, steps: u32) -> bool {
+    let generation = FADE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let steps = steps.max(1);
+    let step_delay = duration / steps;
+    let path = format!("/sys/class/backlight/{}/brightness", device);
+
+    for i in 1..=steps {
+        if FADE_GENERATION.load(Ordering::SeqCst) != generation {
+            return false;
+        }
+
+        let value = from as i64 + (to as i64 - from as i64) * i as i64 / steps as i64;
+        if let Err(e) = fs::write(&path, value.to_string()) {
+            log_error_message(&format!(
+                "Warning: Failed to write brightness at {}: {}. \
+                You may need root privileges or a udev rule to write to this file.",
+                path, e
+            ));
+            return false;
+        }
+
+        if i < steps {
+            tokio::time::sleep(step_delay).await;
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::sysfs;
+
+    #[test]
+    fn capture_reads_the_backlight_as_value_and_percent() {
+        let base = sysfs(
+            "backlight",
+            &[("intel_backlight/brightness", "300\n"), ("intel_backlight/max_brightness", "1000\n")],
+        );
+        let state = capture_from(&base).unwrap();
+        assert_eq!((state.device.as_str(), state.value, state.percent), ("intel_backlight", 300, Some(30)));
+        let _ = fs::remove_dir_all(&base);
+
+        // An unreadable max still captures the raw value
+        let base = sysfs("backlight-no-max", &[("acpi_video0/brightness", "7\n")]);
+        assert_eq!(capture_from(&base).map(|s| (s.value, s.percent)), Some((7, None)));
+        let _ = fs::remove_dir_all(&base);
+
+        let empty = sysfs("no-backlight", &[]);
+        fs::create_dir_all(&empty).unwrap();
+        assert!(capture_from(&empty).is_none());
+        let _ = fs::remove_dir_all(&empty);
+    }
+}
@@ -1,8 +1,9 @@
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}};
 use eyre::Result;
+use once_cell::sync::Lazy;
 use regex::Regex;
 use rune_cfg::{RuneConfig, Value};
-use crate::{log::log_message, utils::is_laptop};
+use crate::{log::{log_error_message, log_message, log_warn_message}, schedule::{self, ScheduleEntry}, utils::{is_laptop, set_chassis_override}};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum IdleActionKind {
@@ -11,6 +12,67 @@ pub enum IdleActionKind {
     Dpms,
     Brightness,
     Custom,
+    /// Ordered steps run one after another with per-step delays
+    Sequence(Vec<SequenceStep>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SequenceStep {
+    pub name: String,
+    /// Seconds to wait after the previous step before running this one
+    pub delay_seconds: u64,
+    pub command: String,
+}
+
+/// How suspend actions put the machine to sleep, from `idle.suspend_method`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SuspendMethod {
+    /// Run the action's command
+    #[default]
+    Command,
+    Suspend,
+    Hibernate,
+    SuspendThenHibernate,
+}
+
+impl SuspendMethod {
+    /// The org.freedesktop.login1.Manager method, None for `command`
+    pub fn logind_method(self) -> Option<&'static str> {
+        match self {
+            SuspendMethod::Command => None,
+            SuspendMethod::Suspend => Some("Suspend"),
+            SuspendMethod::Hibernate => Some("Hibernate"),
+            SuspendMethod::SuspendThenHibernate => Some("SuspendThenHibernate"),
+        }
+    }
+}
+
+impl std::str::FromStr for SuspendMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "command" => Ok(SuspendMethod::Command),
+            "suspend" => Ok(SuspendMethod::Suspend),
+            "hibernate" => Ok(SuspendMethod::Hibernate),
+            "suspend-then-hibernate" => Ok(SuspendMethod::SuspendThenHibernate),
+            other => Err(format!(
+                "invalid suspend method '{}', expected suspend, hibernate, suspend-then-hibernate or command",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SuspendMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SuspendMethod::Command => write!(f, "command"),
+            SuspendMethod::Suspend => write!(f, "suspend"),
+            SuspendMethod::Hibernate => write!(f, "hibernate"),
+            SuspendMethod::SuspendThenHibernate => write!(f, "suspend-then-hibernate"),
+        }
+    }
 }
 
 impl fmt::Display for IdleActionKind {
@@ -21,15 +83,83 @@ impl fmt::Display for IdleActionKind {
             IdleActionKind::Dpms => write!(f, "dpms"),
             IdleActionKind::Brightness => write!(f, "brightness"),
             IdleActionKind::Custom => write!(f, "custom"),
+            IdleActionKind::Sequence(_) => write!(f, "sequence"),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct IdleAction {
+    /// Full key including the power prefix, e.g. `ac.lock-screen`
+    pub name: String,
     pub timeout_seconds: u64,
     pub command: String,
+    /// Set when `command` was given as an array; run directly without a shell
+    pub argv: Option<Vec<String>>,
     pub kind: IdleActionKind,
+    pub enabled: bool,
+    /// Native brightness fade target in percent (brightness actions only)
+    pub dim_to: Option<u32>,
+    pub fade_seconds: u64,
+    /// Routine "fired" logs only show in verbose mode, errors are still logged
+    pub quiet: bool,
+    /// Minimum seconds between two firings of this action's kind
+    pub cooldown_seconds: u64,
+    /// Overrides `idle.command_timeout_seconds` for this action's command
+    pub command_timeout_seconds: Option<u64>,
+    /// Fire again every `timeout_seconds` while idle continues, instead of once per idle period
+    pub repeat: bool,
+    /// Aborted (and its command killed) on activity. Defaults to true for brightness actions
+    pub reversible: bool,
+    /// Brightness actions only: restores brightness when sysfs isn't writable
+    pub restore_command: Option<String>,
+}
+
+/// A key for the virtual keyboard, as an evdev keycode and the XKB keysym it is mapped to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WakeKey {
+    pub code: u32,
+    pub keysym: String,
+}
+
+/// Highest evdev keycode that fits an XKB keymap (keycodes are offset by 8, max 255)
+const MAX_WAKE_KEYCODE: u32 = 247;
+
+impl WakeKey {
+    /// Parse a key name like `shift` or `f24`, or an evdev keycode in decimal or `0x` hex
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().to_ascii_lowercase().replace(['-', '_'], "");
+        let named = |code: u32, keysym: &str| Some(Self { code, keysym: keysym.to_string() });
+
+        match s.as_str() {
+            "shift" | "leftshift" | "shiftl" => named(42, "Shift_L"),
+            "rightshift" | "shiftr" => named(54, "Shift_R"),
+            "ctrl" | "control" | "leftctrl" | "controll" => named(29, "Control_L"),
+            "rightctrl" | "controlr" => named(97, "Control_R"),
+            "alt" | "leftalt" | "altl" => named(56, "Alt_L"),
+            _ => {
+                // F13-F24 sit at 183-194
+                if let Some(n) = s.strip_prefix('f').and_then(|n| n.parse::<u32>().ok())
+                    && (13..=24).contains(&n)
+                {
+                    return named(170 + n, &format!("F{}", n));
+                }
+
+                let code = match s.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => s.parse().ok()?,
+                };
+                // A bare keycode carries no symbol, clients see nothing but the compositor still counts input
+                (1..=MAX_WAKE_KEYCODE).contains(&code).then(|| Self { code, keysym: "NoSymbol".to_string() })
+            }
+        }
+    }
+}
+
+impl fmt::Display for WakeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (keycode {})", self.keysym, self.code)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +168,16 @@ pub enum AppPattern {
     Regex(Regex),
 }
 
+impl AppPattern {
+    /// Case-insensitive equality for literals, a search for regexes
+    pub fn is_match(&self, s: &str) -> bool {
+        match self {
+            AppPattern::Literal(l) => l.eq_ignore_ascii_case(s),
+            AppPattern::Regex(r) => r.is_match(s),
+        }
+    }
+}
+
 impl fmt::Display for AppPattern {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -47,24 +187,180 @@ impl fmt::Display for AppPattern {
     }
 }
 
+/// How serious a config problem is. Only errors fail `stasis check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// May still work at runtime, e.g. a program missing from this shell's PATH
+    Warning,
+    /// The setting was rejected or ignored
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found while parsing the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigWarning {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl ConfigWarning {
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, message: message.into() }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, message: message.into() }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct IdleConfig {
     pub actions: HashMap<String, IdleAction>,
     pub resume_command: Option<String>,
     pub pre_suspend_command: Option<String>,
+    /// Run synchronously on return from idle, before brightness is restored
+    pub pre_resume_command: Option<String>,
+    /// Delay before `resume_command` runs after a wake from sleep, or the
+    /// ceiling on waiting for activity with `resume_wait_for_activity`
+    pub resume_delay_ms: u64,
+    /// After a wake from sleep, hold `resume_command` until the first input
+    pub resume_wait_for_activity: bool,
+    /// Suspend actions start the lock_screen locker and wait for it first
+    pub lock_before_suspend: bool,
+    /// Sleep through logind instead of running the suspend action's command
+    pub suspend_method: SuspendMethod,
+    /// Passed to logind, allowing a polkit prompt when sleeping needs authorization
+    pub suspend_interactive: bool,
+    /// Reload automatically when the config file changes
+    pub auto_reload: bool,
     pub monitor_media: bool,
+    pub ignore_remote_media: bool,
+    pub remote_media_players: Vec<String>,
+    /// Only players matching one of these can inhibit idle, any player when empty
+    pub media_inhibit_players: Vec<AppPattern>,
+    /// Players that never inhibit idle
+    pub media_ignore_players: Vec<AppPattern>,
+    /// Media only inhibits while a sink input is actually outputting audio
+    pub media_require_audio: bool,
+    pub media_poll_seconds: u64,
     pub respect_idle_inhibitors: bool,
+    /// Serve org.freedesktop.ScreenSaver and pause while apps hold an inhibit
+    pub screensaver_inhibit: bool,
+    /// Idle actions are paused while this file exists
+    pub inhibit_lockfile: Option<String>,
     pub inhibit_apps: Vec<AppPattern>,
+    pub app_poll_seconds: u64,
+    /// Inhibit idle while any window is fullscreen
+    pub inhibit_on_fullscreen: bool,
+    pub min_idle_duration: u64,
+    /// Seconds before the first action fires to run `warn_command`, 0 disables
+    pub warn_before_seconds: u64,
+    pub warn_command: Option<String>,
+    /// Run before each idle action fires, a nonzero exit vetoes it until the next check
+    pub pre_action_command: Option<String>,
+    /// Longest `pre_action_command` may run before it counts as a veto
+    pub pre_action_timeout_seconds: u64,
+    /// Milliseconds added to (or, if negative, subtracted from) the
+    /// compositor idle notification timeout
+    pub notification_margin_ms: i64,
+    /// Raw evdev devices read when the libinput seat can't be used
+    pub input_devices: Vec<String>,
+    /// Only key/button presses and pointer motion past `input_jitter_threshold` count as activity
+    pub ignore_input_jitter: bool,
+    /// Pointer travel in pixels that counts as activity with `ignore_input_jitter`
+    pub input_jitter_threshold: f64,
+    /// libinput device names whose events never count as activity
+    pub ignore_input_devices: Vec<AppPattern>,
+    /// Seconds of continuous inhibition before `inhibit_warn_command` runs, 0 disables
+    pub inhibit_warn_after: u64,
+    pub inhibit_warn_command: Option<String>,
+    /// While inhibited, send a synthetic key this often to keep other idle systems awake, 0 disables
+    pub keep_alive_seconds: u64,
+    /// Synthetic key pressed when Stasis itself ends idle (resume, wake from sleep)
+    pub wake_key: Option<WakeKey>,
+    /// Send desktop notifications for the idle warning (normal) and low battery (critical)
+    pub notify: bool,
+    /// Only watch idle on the Wayland seat with this name, all seats otherwise
+    pub seat: Option<String>,
+    /// Timeout-driven actions wait for the first activity after startup
+    pub require_activity_first: bool,
+    /// Suspend never fires within this many seconds of startup or a resume
+    pub min_awake_seconds: u64,
+    /// Lines of stderr included in the log when a command fails
+    pub command_log_lines: usize,
+    /// Log file for Stasis messages and command output, the cache dir default otherwise
+    pub log_file: Option<String>,
+    /// An identical error is logged at most once per this many seconds, 0 logs every one
+    pub log_repeat_window_seconds: u64,
+    /// Reload requests within this many milliseconds are coalesced into the last one
+    pub reload_debounce_ms: u64,
+    /// Commands running longer than this are killed (lockers are only left running)
+    pub command_timeout_seconds: Option<u64>,
+    /// Action commands allowed to run at once, further ones are queued
+    pub max_concurrent_actions: usize,
+    /// `idle.on_battery.timeout_scale`: scales the AC timeouts into derived battery actions
+    pub on_battery_timeout_scale: Option<f64>,
+    /// `idle.on_battery.low_threshold`: percent at or below which `on_low_battery` actions apply
+    pub low_battery_threshold: Option<u8>,
+    /// `idle.battery_timeout_scale`: multiplies every active timeout while on battery,
+    /// on top of `on_battery_timeout_scale` for derived actions
+    pub battery_timeout_scale: Option<f64>,
+    /// Skip Dpms and Brightness actions while on AC power
+    pub disable_display_actions_on_ac: bool,
+    /// `idle.force_laptop` (true) or `idle.force_desktop` (false), None to detect
+    pub force_chassis: Option<bool>,
+    /// Action whose timeout follows the desktop environment's idle-delay
+    pub follow_de_idle_delay: Option<String>,
+    pub triggers: HashMap<String, Vec<String>>,
+    pub schedule: Vec<ScheduleEntry>,
+    /// Problems found while parsing, e.g. actions skipped for a missing timeout
+    pub warnings: Vec<ConfigWarning>,
+}
+
+/// Daemon state shown alongside the config by `stasis info`
+pub struct RuntimeInfo<'a> {
+    pub idle_time: std::time::Duration,
+    pub uptime: std::time::Duration,
+    pub is_inhibited: bool,
+    pub fullscreen_inhibited: bool,
+    pub auto_paused: bool,
+    pub presentation: bool,
+    pub action_states: &'a [(String, bool)],
+    pub warning: Option<&'a str>,
+    pub power_flips_last_hour: usize,
+    pub battery_percent: Option<u8>,
+    /// Time left on a timed `stasis inhibit`
+    pub inhibit_remaining: Option<std::time::Duration>,
+    /// Timeout of the compositor idle notifications, None when not compositor-managed
+    pub compositor_timeout_ms: Option<u32>,
+    /// Action that fires next if idle continues, and the time left until it does
+    pub next_action: Option<(String, std::time::Duration)>,
+    /// What last reset the idle timer, and how long ago
+    pub last_activity: Option<(crate::idle_timer::ResetSource, std::time::Duration)>,
 }
 
 impl IdleConfig {
     /// Pretty-print config, optionally including runtime info
-    pub fn pretty_print(
-        &self,
-        idle_time: Option<std::time::Duration>,
-        uptime: Option<std::time::Duration>,
-        is_inhibited: Option<bool>,
-    ) -> String {
+    pub fn pretty_print(&self, runtime: Option<&RuntimeInfo>) -> String {
         let mut out = String::new();
 
         // General settings
@@ -77,14 +373,50 @@ impl IdleConfig {
             "  PreSuspendCommand  = {}\n",
             self.pre_suspend_command.as_deref().unwrap_or("-")
         ));
+        out.push_str(&format!(
+            "  PreResumeCommand   = {}\n",
+            self.pre_resume_command.as_deref().unwrap_or("-")
+        ));
+        out.push_str(&format!("  LockBeforeSuspend  = {}\n", self.lock_before_suspend));
+        out.push_str(&format!("  SuspendMethod      = {}\n", self.suspend_method));
+        if self.resume_wait_for_activity {
+            let ceiling = match self.resume_delay_ms {
+                0 => String::new(),
+                ms => format!(", at most {}ms", ms),
+            };
+            out.push_str(&format!("  ResumeAfterWake    = on first activity{}\n", ceiling));
+        } else if self.resume_delay_ms > 0 {
+            out.push_str(&format!("  ResumeAfterWake    = after {}ms\n", self.resume_delay_ms));
+        }
+        out.push_str(&format!(
+            "  AutoReload         = {}\n",
+            if self.auto_reload { "true" } else { "false" }
+        ));
         out.push_str(&format!(
             "  MonitorMedia       = {}\n",
             if self.monitor_media { "true" } else { "false" }
         ));
+        out.push_str(&format!(
+            "  IgnoreRemoteMedia  = {}\n",
+            if self.ignore_remote_media { "true" } else { "false" }
+        ));
+        out.push_str(&format!("  MediaRequireAudio  = {}\n", self.media_require_audio));
+        if !self.media_inhibit_players.is_empty() {
+            let players = self.media_inhibit_players.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            out.push_str(&format!("  MediaInhibitPlayers = {}\n", players.join(",")));
+        }
+        if !self.media_ignore_players.is_empty() {
+            let players = self.media_ignore_players.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            out.push_str(&format!("  MediaIgnorePlayers = {}\n", players.join(",")));
+        }
         out.push_str(&format!(
             "  RespectInhibitors  = {}\n",
             if self.respect_idle_inhibitors { "true" } else { "false" }
         ));
+        out.push_str(&format!(
+            "  ScreenSaverInhibit = {}\n",
+            if self.screensaver_inhibit { "true" } else { "false" }
+        ));
 
         let apps = if self.inhibit_apps.is_empty() {
             "-".to_string()
@@ -96,16 +428,123 @@ impl IdleConfig {
                 .join(",")
         };
         out.push_str(&format!("  InhibitApps        = {}\n", apps));
-
-        // Optional runtime info
-        if let Some(idle) = idle_time {
-            out.push_str(&format!("  IdleTime           = {}\n", crate::utils::format_duration(idle)));
+        out.push_str(&format!("  AppPollInterval    = {}s\n", self.app_poll_seconds));
+        if !self.ignore_input_devices.is_empty() {
+            let devices = self.ignore_input_devices.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+            out.push_str(&format!("  IgnoreInputDevices = {}\n", devices.join(",")));
+        }
+        out.push_str(&format!(
+            "  InhibitFullscreen  = {}\n",
+            if self.inhibit_on_fullscreen { "true" } else { "false" }
+        ));
+        out.push_str(&format!("  MinIdleDuration    = {}s\n", self.min_idle_duration));
+        out.push_str(&format!("  MaxConcurrent      = {}\n", self.max_concurrent_actions));
+        out.push_str(&format!("  CommandLogLines    = {}\n", self.command_log_lines));
+        if let Some(path) = &self.inhibit_lockfile {
+            out.push_str(&format!("  InhibitLockfile    = {}\n", path));
+        }
+        if let Some(path) = &self.log_file {
+            out.push_str(&format!("  LogFile            = {}\n", path));
+        }
+        out.push_str(&format!("  ReloadDebounce     = {}ms\n", self.reload_debounce_ms));
+        out.push_str(&format!("  LogRepeatWindow    = {}s\n", self.log_repeat_window_seconds));
+        if let Some(seconds) = self.command_timeout_seconds {
+            out.push_str(&format!("  CommandTimeout     = {}s\n", seconds));
+        }
+        out.push_str(&format!("  RequireActivity    = {}\n", self.require_activity_first));
+        out.push_str(&format!("  Seat               = {}\n", self.seat.as_deref().unwrap_or("all")));
+        out.push_str(&format!("  Notify             = {}\n", self.notify));
+        if self.ignore_input_jitter {
+            out.push_str(&format!("  IgnoreInputJitter  = below {}px\n", self.input_jitter_threshold));
         }
-        if let Some(up) = uptime {
-            out.push_str(&format!("  Uptime             = {}\n", crate::utils::format_duration(up)));
+        if self.min_awake_seconds > 0 {
+            out.push_str(&format!("  MinAwake           = {}s before suspend\n", self.min_awake_seconds));
         }
-        if let Some(inhibited) = is_inhibited {
-            out.push_str(&format!("  IdleInhibited      = {}\n", inhibited));
+        if self.notification_margin_ms != 0 {
+            out.push_str(&format!("  NotifyMargin       = {}ms\n", self.notification_margin_ms));
+        }
+        if let Some(cmd) = &self.warn_command {
+            out.push_str(&format!("  WarnCommand        = {} ({}s before)\n", cmd, self.warn_before_seconds));
+        }
+        if let Some(cmd) = &self.pre_action_command {
+            out.push_str(&format!("  PreActionCommand   = {} ({}s timeout)\n", cmd, self.pre_action_timeout_seconds));
+        }
+        if self.inhibit_warn_after > 0 {
+            out.push_str(&format!(
+                "  InhibitWarn        = after {} -> {}\n",
+                crate::utils::format_duration(std::time::Duration::from_secs(self.inhibit_warn_after)),
+                self.inhibit_warn_command.as_deref().unwrap_or("log only")
+            ));
+        }
+        if self.keep_alive_seconds > 0 {
+            out.push_str(&format!("  KeepAlive          = every {}s while inhibited\n", self.keep_alive_seconds));
+        }
+        if let Some(key) = &self.wake_key {
+            out.push_str(&format!("  WakeKey            = {}\n", key));
+        }
+        out.push_str(&format!(
+            "  NoDisplayOnAC      = {}\n",
+            if self.disable_display_actions_on_ac { "true" } else { "false" }
+        ));
+        out.push_str(&format!(
+            "  ForceChassis       = {}\n",
+            match self.force_chassis {
+                Some(true) => "laptop",
+                Some(false) => "desktop",
+                None => "-",
+            }
+        ));
+        out.push_str(&format!(
+            "  FollowDEIdleDelay  = {}\n",
+            self.follow_de_idle_delay.as_deref().unwrap_or("-")
+        ));
+        if let Some(scale) = self.on_battery_timeout_scale {
+            out.push_str(&format!("  OnBatteryScale     = {}\n", scale));
+        }
+        if let Some(scale) = self.battery_timeout_scale {
+            out.push_str(&format!("  BatteryScale       = {}\n", scale));
+        }
+        if let Some(threshold) = self.low_battery_threshold {
+            out.push_str(&format!("  LowBattery         = at or below {}%\n", threshold));
+        }
+        let schedule = if self.schedule.is_empty() {
+            "-".to_string()
+        } else {
+            self.schedule
+                .iter()
+                .map(|e| format!("{}(x{})", e.name, e.multiplier))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        out.push_str(&format!("  Schedule           = {}\n", schedule));
+
+        // Optional runtime info
+        if let Some(rt) = runtime {
+            out.push_str(&format!("  IdleTime           = {}\n", crate::utils::format_duration(rt.idle_time)));
+            out.push_str(&format!("  Uptime             = {}\n", crate::utils::format_duration(rt.uptime)));
+            out.push_str(&format!("  IdleInhibited      = {}\n", rt.is_inhibited));
+            out.push_str(&format!("  FullscreenInhibit  = {}\n", rt.fullscreen_inhibited));
+            out.push_str(&format!("  AutoPaused         = {}\n", rt.auto_paused));
+            out.push_str(&format!("  Presentation       = {}\n", rt.presentation));
+            if let Some(remaining) = rt.inhibit_remaining {
+                out.push_str(&format!("  InhibitRemaining   = {}\n", crate::utils::format_duration(remaining)));
+            }
+            if let Some((source, ago)) = rt.last_activity {
+                out.push_str(&format!("  LastActivity       = {} {} ago\n", source, crate::utils::format_duration(ago)));
+            }
+            if let Some((name, remaining)) = &rt.next_action {
+                out.push_str(&format!("  NextAction         = {} in {}\n", name, crate::utils::format_duration(*remaining)));
+            }
+            if let Some(warning) = rt.warning {
+                out.push_str(&format!("  IdleWarning        = {}\n", warning));
+            }
+            out.push_str(&format!("  PowerFlipsLastHour = {}\n", rt.power_flips_last_hour));
+            if let Some(ms) = rt.compositor_timeout_ms {
+                out.push_str(&format!("  CompositorTimeout  = {}ms\n", ms));
+            }
+            if let Some(percent) = rt.battery_percent {
+                out.push_str(&format!("  Battery            = {}%\n", percent));
+            }
         }
 
         // Actions
@@ -131,32 +570,107 @@ impl IdleConfig {
             sorted.sort_by(|a, b| a.0.cmp(b.0));
 
             for (key, action) in sorted {
+                let dim = match action.dim_to {
+                    Some(percent) => format!(" DimTo={}% Fade={}s", percent, action.fade_seconds),
+                    None => String::new(),
+                };
+                let steps = match &action.kind {
+                    IdleActionKind::Sequence(steps) => format!(
+                        " Steps=[{}]",
+                        steps
+                            .iter()
+                            .map(|s| format!("{}@+{}s", s.name, s.delay_seconds))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    _ => String::new(),
+                };
+                let active = runtime
+                    .and_then(|rt| rt.action_states.iter().find(|(name, _)| name == key))
+                    .map(|(_, is_idle)| format!(" Active={}", is_idle))
+                    .unwrap_or_default();
                 out.push_str(&format!(
-                    "    {:<20} Timeout={} Kind={} Command=\"{}\"\n",
+                    "    {:<20} Timeout={} Kind={} Command=\"{}\"{}{}{}{}\n",
                     key,
                     action.timeout_seconds,
                     action.kind,
-                    action.command
+                    action.command,
+                    dim,
+                    steps,
+                    active,
+                    if action.enabled { "" } else { " (disabled)" }
                 ));
             }
         }
 
+        // Manual triggers
+        if !self.triggers.is_empty() {
+            out.push_str("\nTriggers:\n");
+
+            let mut sorted: Vec<_> = self.triggers.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+
+            for (name, commands) in sorted {
+                out.push_str(&format!("    {:<20} Commands=\"{}\"\n", name, commands.join(" ; ")));
+            }
+        }
+
         out
     }
 }
 
+/// MPRIS players treated as remote when `ignore_remote_media` is set and
+/// no `remote_media_players` list is configured (browser tabs, casting, phones)
+const DEFAULT_REMOTE_MEDIA_PLAYERS: &[&str] = &["firefox", "chromium", "chrome", "brave", "kdeconnect"];
+
 // --- Helpers ---
 
-fn parse_app_pattern(s: &str) -> Result<AppPattern> {
+fn parse_app_pattern(s: &str, key: &str, warnings: &mut Vec<ConfigWarning>) -> AppPattern {
     let regex_meta = ['.', '*', '+', '?', '(', ')', '[', ']', '{', '}', '|', '\\', '^', '$'];
     if s.chars().any(|c| regex_meta.contains(&c)) {
-        let re = Regex::new(s)?;
-        Ok(AppPattern::Regex(re))
+        compile_app_regex(s, key, warnings)
     } else {
-        Ok(AppPattern::Literal(s.to_string()))
+        AppPattern::Literal(s.to_string())
     }
 }
 
+/// Compile a regex pattern from `key`, falling back to a literal match if it is invalid
+fn compile_app_regex(s: &str, key: &str, warnings: &mut Vec<ConfigWarning>) -> AppPattern {
+    match Regex::new(s) {
+        Ok(re) => AppPattern::Regex(re),
+        Err(e) => {
+            warnings.push(ConfigWarning::error(format!(
+                "{}: invalid regex \"{}\": {}, matching it as a literal name instead",
+                key, s, e
+            )));
+            AppPattern::Literal(s.to_string())
+        }
+    }
+}
+
+/// Expand `${VAR}` and `$VAR` from the environment. Unset variables are left
+/// as written and reported in `warnings`
+fn expand_env(s: &str, warnings: &mut Vec<ConfigWarning>) -> String {
+    expand_vars(s, |name| std::env::var(name).ok(), warnings)
+}
+
+/// `expand_env` with the variable lookup passed in
+fn expand_vars(s: &str, lookup: impl Fn(&str) -> Option<String>, warnings: &mut Vec<ConfigWarning>) -> String {
+    static ENV_VAR: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}|\$([A-Za-z_][A-Za-z0-9_]*)").unwrap()
+    });
+
+    ENV_VAR
+        .replace_all(s, |caps: &regex::Captures| {
+            let name = caps.get(1).or_else(|| caps.get(2)).map(|m| m.as_str()).unwrap_or_default();
+            lookup(name).unwrap_or_else(|| {
+                warnings.push(ConfigWarning::error(format!("${} is not set, leaving it unexpanded in \"{}\"", name, s)));
+                caps[0].to_string()
+            })
+        })
+        .into_owned()
+}
+
 // Helper to try both - and _ variants of a key
 fn try_get_string(config: &RuneConfig, base_path: &str) -> Option<String> {
     // Try hyphenated version first
@@ -190,6 +704,37 @@ fn try_get_bool(config: &RuneConfig, base_path: &str, default: bool) -> bool {
     default
 }
 
+fn try_get_u64(config: &RuneConfig, base_path: &str, default: u64) -> u64 {
+    match try_get_value(config, base_path) {
+        Some(Value::Number(n)) => n as u64,
+        Some(Value::String(s)) => s.parse::<u64>().unwrap_or(default),
+        _ => default,
+    }
+}
+
+fn try_get_i64(config: &RuneConfig, base_path: &str, default: i64) -> i64 {
+    match try_get_value(config, base_path) {
+        Some(Value::Number(n)) => n as i64,
+        Some(Value::String(s)) => s.parse::<i64>().unwrap_or(default),
+        _ => default,
+    }
+}
+
+/// A list of app-style patterns: plain strings, or regexes when they contain regex syntax
+fn try_get_patterns(config: &RuneConfig, base_path: &str, warnings: &mut Vec<ConfigWarning>) -> Vec<AppPattern> {
+    match try_get_value(config, base_path) {
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .filter_map(|v| match v {
+                Value::String(s) => Some(parse_app_pattern(s, base_path, warnings)),
+                Value::Regex(s) => Some(compile_app_regex(s, base_path, warnings)),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 fn try_get_value(config: &RuneConfig, base_path: &str) -> Option<Value> {
     // Try hyphenated version first
     let hyphenated = base_path.replace('_', "-");
@@ -238,13 +783,82 @@ fn is_special_key(key: &str) -> bool {
         key,
         "resume_command" | "resume-command"
             | "pre_suspend_command" | "pre-suspend-command"
+            | "pre_resume_command" | "pre-resume-command"
+            | "resume_delay_ms" | "resume-delay-ms"
+            | "resume_wait_for_activity" | "resume-wait-for-activity"
+            | "lock_before_suspend" | "lock-before-suspend"
+            | "suspend_method" | "suspend-method"
+            | "suspend_interactive" | "suspend-interactive"
+            | "auto_reload" | "auto-reload"
             | "monitor_media" | "monitor-media"
+            | "ignore_remote_media" | "ignore-remote-media"
+            | "remote_media_players" | "remote-media-players"
+            | "media_inhibit_players" | "media-inhibit-players"
+            | "media_ignore_players" | "media-ignore-players"
+            | "media_require_audio" | "media-require-audio"
+            | "media_poll_seconds" | "media-poll-seconds"
             | "respect_idle_inhibitors" | "respect-idle-inhibitors"
+            | "screensaver_inhibit" | "screensaver-inhibit"
+            | "inhibit_lockfile" | "inhibit-lockfile"
             | "inhibit_apps" | "inhibit-apps"
+            | "app_poll_seconds" | "app-poll-seconds"
+            | "inhibit_on_fullscreen" | "inhibit-on-fullscreen"
+            | "min_idle_duration" | "min-idle-duration"
+            | "max_concurrent_actions" | "max-concurrent-actions"
+            | "input_devices" | "input-devices"
+            | "ignore_input_jitter" | "ignore-input-jitter"
+            | "input_jitter_threshold" | "input-jitter-threshold"
+            | "ignore_input_devices" | "ignore-input-devices"
+            | "notification_margin_ms" | "notification-margin-ms"
+            | "warn_before_seconds" | "warn-before-seconds"
+            | "warn_command" | "warn-command"
+            | "pre_action_command" | "pre-action-command"
+            | "pre_action_timeout_seconds" | "pre-action-timeout-seconds"
+            | "inhibit_warn_after" | "inhibit-warn-after"
+            | "inhibit_warn_command" | "inhibit-warn-command"
+            | "keep_alive_seconds" | "keep-alive-seconds"
+            | "wake_key" | "wake-key"
+            | "command_log_lines" | "command-log-lines"
+            | "command_timeout_seconds" | "command-timeout-seconds"
+            | "log_file" | "log-file"
+            | "reload_debounce_ms" | "reload-debounce-ms"
+            | "log_repeat_window_seconds" | "log-repeat-window-seconds"
+            | "min_awake_seconds" | "min-awake-seconds"
+            | "require_activity_first" | "require-activity-first"
+            | "seat"
+            | "notify"
+            | "triggers"
+            | "schedule"
+            | "on_ac" | "on-ac"
+            | "on_battery" | "on-battery"
+            | "timeout_scale" | "timeout-scale"
+            | "low_threshold" | "low-threshold"
+            | "on_low_battery" | "on-low-battery"
+            | "battery_timeout_scale" | "battery-timeout-scale"
+            | "no_display_actions" | "no-display-actions"
+            | "disable_display_actions_on_ac" | "disable-display-actions-on-ac"
+            | "force_laptop" | "force-laptop"
+            | "force_desktop" | "force-desktop"
+            | "follow_de_idle_delay" | "follow-de-idle-delay"
     )
 }
 
-fn collect_actions(config: &RuneConfig, path: &str, prefix: &str) -> HashMap<String, IdleAction> {
+/// `idle.suspend_method`, falling back to `command` when missing or invalid
+fn try_get_suspend_method(config: &RuneConfig) -> SuspendMethod {
+    try_get_string(config, "idle.suspend_method")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Fields recognised inside an action block
+const ACTION_FIELDS: &[&str] = &["command", "timeout", "enabled", "dim_to", "fade_seconds", "steps", "on", "quiet", "cooldown_seconds", "command_timeout_seconds", "repeat", "reversible", "restore_command"];
+
+fn collect_actions(
+    config: &RuneConfig,
+    path: &str,
+    prefix: &str,
+    warnings: &mut Vec<ConfigWarning>,
+) -> HashMap<String, IdleAction> {
     let mut actions = HashMap::new();
     let keys = try_get_keys(config, path);
 
@@ -253,21 +867,19 @@ fn collect_actions(config: &RuneConfig, path: &str, prefix: &str) -> HashMap<Str
             continue;
         }
 
-        // Command must exist
-        let command = match try_get_string(config, &format!("{}.{}.command", path, key)) {
-            Some(cmd) => cmd,
-            None => continue,
-        };
+        let key_path = format!("{}.{}", path, key);
+        let fields = try_get_keys(config, &key_path);
+        if fields.is_empty() {
+            warnings.push(ConfigWarning::error(format!("{}: unknown setting or empty action block", key_path)));
+            continue;
+        }
 
-        // Timeout must exist and parse, otherwise skip
-        let timeout_seconds = match try_get_value(config, &format!("{}.{}.timeout", path, key)) {
-            Some(Value::Number(n)) => n as u64,
-            Some(Value::String(s)) => match s.parse::<u64>() {
-                Ok(n) => n,
-                _ => continue,
-            },
-            _ => continue,
-        };
+        // Catch typos like `timout` that would otherwise be ignored
+        for field in &fields {
+            if !ACTION_FIELDS.contains(&field.replace('-', "_").as_str()) {
+                warnings.push(ConfigWarning::error(format!("{}.{}: unknown field", key_path, field)));
+            }
+        }
 
         // Determine kind
         let kind = match key.as_str() {
@@ -275,63 +887,545 @@ fn collect_actions(config: &RuneConfig, path: &str, prefix: &str) -> HashMap<Str
             "suspend" => IdleActionKind::Suspend,
             "dpms" => IdleActionKind::Dpms,
             "brightness" => IdleActionKind::Brightness,
+            "sequence" => {
+                let steps = collect_sequence_steps(config, &format!("{}.steps", key_path), warnings);
+                if steps.is_empty() {
+                    warnings.push(ConfigWarning::error(format!("{}: sequence has no valid steps, action skipped", key_path)));
+                    continue;
+                }
+                IdleActionKind::Sequence(steps)
+            }
             _ => IdleActionKind::Custom,
         };
 
-        actions.insert(
-            format!("{}.{}", prefix, normalize_key(&key)),
-            IdleAction {
-                timeout_seconds,
-                command,
-                kind,
-            },
+        // Native dimming replaces the command for brightness actions
+        let dim_to = if kind == IdleActionKind::Brightness {
+            match try_get_value(config, &format!("{}.{}.dim_to", path, key)) {
+                Some(Value::Number(n)) => Some((n as u32).min(100)),
+                Some(Value::String(s)) => s.trim_end_matches('%').parse::<u32>().ok().map(|n| n.min(100)),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        let fade_seconds = try_get_u64(config, &format!("{}.{}.fade_seconds", path, key), 0);
+        let restore_command = if kind == IdleActionKind::Brightness {
+            try_get_string(config, &format!("{}.restore_command", key_path)).map(|cmd| expand_env(&cmd, warnings))
+        } else {
+            None
+        };
+        check_program(
+            &format!("{}.restore_command", key_path),
+            restore_command.as_deref().and_then(crate::utils::command_program),
+            warnings,
         );
+
+        // Command must exist unless dimming natively. A string runs through
+        // `sh -c`, an array is executed directly as argv.
+        let (command, argv) = match try_get_value(config, &format!("{}.command", key_path)) {
+            Some(Value::String(cmd)) => (expand_env(&cmd, warnings), None),
+            Some(Value::Array(arr)) => {
+                let argv: Vec<String> = arr
+                    .iter()
+                    .filter_map(|v| match v {
+                        Value::String(s) => Some(expand_env(s, warnings)),
+                        _ => None,
+                    })
+                    .collect();
+                if argv.is_empty() {
+                    warnings.push(ConfigWarning::error(format!("{}: `command` array is empty, action skipped", key_path)));
+                    continue;
+                }
+                (argv.join(" "), Some(argv))
+            }
+            _ if dim_to.is_some() || matches!(kind, IdleActionKind::Sequence(_)) => (String::new(), None),
+            // Suspend can go through logind instead of a command
+            _ if kind == IdleActionKind::Suspend && try_get_suspend_method(config) != SuspendMethod::Command => (String::new(), None),
+            // Without a command, dpms uses wlr-output-power-management
+            _ if cfg!(feature = "wlr_output_power") && kind == IdleActionKind::Dpms => (String::new(), None),
+            _ => {
+                warnings.push(ConfigWarning::error(format!("{}: missing `command`, action skipped", key_path)));
+                continue;
+            }
+        };
+        // Suspend may legitimately be empty since it still runs the pre-suspend command
+        if command.trim().is_empty()
+            && dim_to.is_none()
+            && !matches!(kind, IdleActionKind::Suspend | IdleActionKind::Sequence(_))
+            && !(cfg!(feature = "wlr_output_power") && kind == IdleActionKind::Dpms)
+        {
+            warnings.push(ConfigWarning::warning(format!("{}: `command` is empty, action will do nothing when it fires", key_path)));
+        }
+
+        // Catch a missing binary once here instead of on every run
+        let program = match &argv {
+            Some(argv) => argv.first().map(String::as_str),
+            None => crate::utils::command_program(&command),
+        };
+        check_program(&key_path, program, warnings);
+
+        // Timeout must exist and parse, otherwise skip
+        let timeout = match try_get_value(config, &format!("{}.{}.timeout", path, key)) {
+            Some(Value::Number(n)) => (n, n.to_string()),
+            Some(Value::String(s)) => (s.trim().parse::<f64>().unwrap_or(f64::NAN), s),
+            _ => {
+                warnings.push(ConfigWarning::error(format!("{}: missing `timeout`, action skipped", key_path)));
+                continue;
+            }
+        };
+        let timeout_seconds = match timeout {
+            (n, _) if n.is_finite() && n >= 0.0 => {
+                // Timeouts are whole seconds, say so rather than silently truncating
+                let seconds = n.round();
+                if seconds != n {
+                    warnings.push(ConfigWarning::warning(format!("{}: `timeout` {} rounded to {}s", key_path, n, seconds)));
+                }
+                seconds as u64
+            }
+            (_, raw) => {
+                warnings.push(ConfigWarning::error(format!("{}: invalid `timeout` \"{}\", action skipped", key_path, raw)));
+                continue;
+            }
+        };
+
+        let enabled = try_get_bool(config, &format!("{}.{}.enabled", path, key), true);
+        let quiet = try_get_bool(config, &format!("{}.{}.quiet", path, key), false);
+        let cooldown_seconds = try_get_u64(config, &format!("{}.{}.cooldown_seconds", path, key), 0);
+        let repeat = try_get_bool(config, &format!("{}.{}.repeat", path, key), false);
+        let reversible = try_get_bool(config, &format!("{}.{}.reversible", path, key), kind == IdleActionKind::Brightness);
+        let command_timeout_seconds = Some(try_get_u64(config, &format!("{}.{}.command_timeout_seconds", path, key), 0))
+            .filter(|&n| n > 0);
+
+        // An `on` list registers the action for each listed power source
+        let prefixes = match try_get_value(config, &format!("{}.on", key_path)) {
+            Some(_) if prefix == "desktop" => {
+                warnings.push(ConfigWarning::error(format!("{}.on: only applies inside on_ac/on_battery blocks, ignored", key_path)));
+                vec![prefix.to_string()]
+            }
+            Some(Value::Array(arr)) => {
+                let mut prefixes = Vec::new();
+                for v in arr {
+                    match v {
+                        Value::String(s) if s == "ac" || s == "battery" => {
+                            if !prefixes.contains(&s) {
+                                prefixes.push(s);
+                            }
+                        }
+                        other => warnings.push(ConfigWarning::error(format!("{}.on: invalid power source {:?}, expected \"ac\" or \"battery\"", key_path, other))),
+                    }
+                }
+                if prefixes.is_empty() {
+                    warnings.push(ConfigWarning::error(format!("{}.on: no valid power sources, action skipped", key_path)));
+                    continue;
+                }
+                prefixes
+            }
+            Some(_) => {
+                warnings.push(ConfigWarning::error(format!("{}.on: expected a list like [\"ac\", \"battery\"], ignored", key_path)));
+                vec![prefix.to_string()]
+            }
+            None => vec![prefix.to_string()],
+        };
+
+        for prefix in prefixes {
+            let name = format!("{}.{}", prefix, normalize_key(&key));
+            actions.insert(
+                name.clone(),
+                IdleAction {
+                    name,
+                    timeout_seconds,
+                    command: command.clone(),
+                    argv: argv.clone(),
+                    kind: kind.clone(),
+                    enabled,
+                    dim_to,
+                    fade_seconds,
+                    quiet,
+                    cooldown_seconds,
+                    command_timeout_seconds,
+                    repeat,
+                    reversible,
+                    restore_command: restore_command.clone(),
+                },
+            );
+        }
     }
 
     actions
 }
 
-pub fn load_config(path: &str) -> Result<IdleConfig> {
-    let config = RuneConfig::from_file(path)?;
+/// Warn when a command's program can't be found. Only a warning, PATH may
+/// differ once the command actually runs.
+fn check_program(what: &str, program: Option<&str>, warnings: &mut Vec<ConfigWarning>) {
+    if let Some(program) = program
+        && !crate::utils::executable_exists(program)
+    {
+        warnings.push(ConfigWarning::warning(format!("{}: `{}` not found in PATH, the command will fail when it fires", what, program)));
+    }
+}
+
+/// Collect the ordered steps of a `sequence` action
+fn collect_sequence_steps(config: &RuneConfig, path: &str, warnings: &mut Vec<ConfigWarning>) -> Vec<SequenceStep> {
+    let mut steps = Vec::new();
+
+    for key in try_get_keys(config, path) {
+        let base = format!("{}.{}", path, key);
+
+        let Some(command) = try_get_string(config, &format!("{}.command", base)) else {
+            warnings.push(ConfigWarning::error(format!("{}: missing `command`, step skipped", base)));
+            continue;
+        };
+
+        let command = expand_env(&command, warnings);
+        check_program(&base, crate::utils::command_program(&command), warnings);
+        steps.push(SequenceStep {
+            name: normalize_key(&key),
+            delay_seconds: try_get_u64(config, &format!("{}.delay", base), 0),
+            command,
+        });
+    }
+
+    steps
+}
+
+/// Collect named, manually-triggered command sequences (never bound to a timeout)
+fn collect_triggers(config: &RuneConfig, path: &str, warnings: &mut Vec<ConfigWarning>) -> HashMap<String, Vec<String>> {
+    let mut triggers = HashMap::new();
+
+    for key in try_get_keys(config, path) {
+        let base = format!("{}.{}", path, key);
+
+        // Either a `commands` list run in order, or a single `command`
+        let commands: Vec<String> = match try_get_value(config, &format!("{}.commands", base)) {
+            Some(Value::Array(arr)) => arr
+                .iter()
+                .filter_map(|v| match v {
+                    Value::String(s) => Some(expand_env(s, warnings)),
+                    _ => None,
+                })
+                .collect(),
+            _ => match try_get_string(config, &format!("{}.command", base)) {
+                Some(cmd) => vec![expand_env(&cmd, warnings)],
+                None => continue,
+            },
+        };
+
+        if commands.is_empty() {
+            continue;
+        }
+
+        triggers.insert(normalize_key(&key), commands);
+    }
+
+    triggers
+}
+
+/// Collect schedule entries that scale action timeouts by day/time
+fn collect_schedule(config: &RuneConfig, path: &str, warnings: &mut Vec<ConfigWarning>) -> Vec<ScheduleEntry> {
+    let mut entries = Vec::new();
+
+    for key in try_get_keys(config, path) {
+        let base = format!("{}.{}", path, key);
+
+        let multiplier = match try_get_value(config, &format!("{}.multiplier", base)) {
+            Some(Value::Number(n)) if n > 0.0 => n,
+            Some(Value::String(s)) => match s.parse::<f64>() {
+                Ok(n) if n > 0.0 => n,
+                _ => {
+                    warnings.push(ConfigWarning::error(format!("{}: invalid multiplier \"{}\", skipping", base, s)));
+                    continue;
+                }
+            },
+            _ => {
+                warnings.push(ConfigWarning::error(format!("{}: missing or invalid multiplier, skipping", base)));
+                continue;
+            }
+        };
+
+        let mut days = Vec::new();
+        match try_get_value(config, &format!("{}.days", base)) {
+            Some(Value::Array(arr)) => {
+                for v in &arr {
+                    if let Value::String(s) = v {
+                        match schedule::parse_days(s) {
+                            Some(d) => days.extend(d),
+                            None => warnings.push(ConfigWarning::error(format!("{}: unknown day \"{}\"", base, s))),
+                        }
+                    }
+                }
+            }
+            Some(Value::String(s)) => match schedule::parse_days(&s) {
+                Some(d) => days.extend(d),
+                None => warnings.push(ConfigWarning::error(format!("{}: unknown day \"{}\"", base, s))),
+            },
+            _ => {}
+        }
+
+        let window = match try_get_string(config, &format!("{}.hours", base)) {
+            Some(s) => match schedule::parse_time_window(&s) {
+                Some(w) => Some(w),
+                None => {
+                    warnings.push(ConfigWarning::error(format!("{}: invalid hours \"{}\" (expected HH:MM-HH:MM), skipping", base, s)));
+                    continue;
+                }
+            },
+            None => None,
+        };
+
+        entries.push(ScheduleEntry {
+            name: normalize_key(&key),
+            days,
+            window,
+            multiplier,
+        });
+    }
+
+    entries
+}
+
+pub fn load_config(path: &Path) -> Result<IdleConfig> {
+    parse_config(&RuneConfig::from_file(path)?)
+}
+
+/// Just `idle.log_file`, so the daemon can log there before the full parse
+/// reports anything. Problems with it are reported by the full parse.
+pub fn read_log_file(path: &Path) -> Option<PathBuf> {
+    let config = RuneConfig::from_file(path).ok()?;
+    try_get_string(&config, "idle.log_file").map(|p| PathBuf::from(expand_env(&p, &mut Vec::new())))
+}
+
+/// Parse config text without a file, for tests
+#[cfg(test)]
+pub fn load_config_str(source: &str) -> Result<IdleConfig> {
+    parse_config(&RuneConfig::from_str(source)?)
+}
+
+fn parse_config(config: &RuneConfig) -> Result<IdleConfig> {
+    let mut warnings = Vec::new();
 
     // --- General Settings ---
-    let resume_command = try_get_string(&config, "idle.resume_command");
-    let pre_suspend_command = try_get_string(&config, "idle.pre_suspend_command");
-    let monitor_media = try_get_bool(&config, "idle.monitor_media", true);
-    let respect_idle_inhibitors = try_get_bool(&config, "idle.respect_idle_inhibitors", true);
+    let resume_command = try_get_string(config, "idle.resume_command").map(|c| expand_env(&c, &mut warnings));
+    let pre_suspend_command = try_get_string(config, "idle.pre_suspend_command").map(|c| expand_env(&c, &mut warnings));
+    let pre_resume_command = try_get_string(config, "idle.pre_resume_command").map(|c| expand_env(&c, &mut warnings));
+    let resume_delay_ms = try_get_u64(config, "idle.resume_delay_ms", 0);
+    let resume_wait_for_activity = try_get_bool(config, "idle.resume_wait_for_activity", false);
+    let lock_before_suspend = try_get_bool(config, "idle.lock_before_suspend", false);
+    let suspend_interactive = try_get_bool(config, "idle.suspend_interactive", false);
+    let auto_reload = try_get_bool(config, "idle.auto_reload", false);
+    let monitor_media = try_get_bool(config, "idle.monitor_media", true);
+    let ignore_remote_media = try_get_bool(config, "idle.ignore_remote_media", false);
+    let media_poll_seconds = try_get_u64(config, "idle.media_poll_seconds", 2).max(1);
+    let respect_idle_inhibitors = try_get_bool(config, "idle.respect_idle_inhibitors", true);
+    let screensaver_inhibit = try_get_bool(config, "idle.screensaver_inhibit", true);
+    let inhibit_lockfile = try_get_string(config, "idle.inhibit_lockfile").map(|p| expand_env(&p, &mut warnings));
+    let min_idle_duration = try_get_u64(config, "idle.min_idle_duration", 0);
+    let app_poll_seconds = try_get_u64(config, "idle.app_poll_seconds", 4).max(1);
+    let inhibit_on_fullscreen = try_get_bool(config, "idle.inhibit_on_fullscreen", false);
+    let warn_before_seconds = try_get_u64(config, "idle.warn_before_seconds", 0);
+    let warn_command = try_get_string(config, "idle.warn_command").map(|c| expand_env(&c, &mut warnings));
+    let pre_action_command = try_get_string(config, "idle.pre_action_command").map(|c| expand_env(&c, &mut warnings));
+    let pre_action_timeout_seconds = try_get_u64(config, "idle.pre_action_timeout_seconds", 5).max(1);
+    let inhibit_warn_after = try_get_u64(config, "idle.inhibit_warn_after", 0);
+    let inhibit_warn_command = try_get_string(config, "idle.inhibit_warn_command").map(|c| expand_env(&c, &mut warnings));
+    let keep_alive_seconds = try_get_u64(config, "idle.keep_alive_seconds", 0);
+    let notification_margin_ms = try_get_i64(config, "idle.notification_margin_ms", 0);
+    let max_concurrent_actions = try_get_u64(config, "idle.max_concurrent_actions", 10).max(1) as usize;
+    let command_log_lines = try_get_u64(config, "idle.command_log_lines", 5) as usize;
+    let command_timeout_seconds = Some(try_get_u64(config, "idle.command_timeout_seconds", 0)).filter(|&n| n > 0);
+    let log_file = try_get_string(config, "idle.log_file").map(|p| expand_env(&p, &mut warnings));
+    let reload_debounce_ms = try_get_u64(config, "idle.reload_debounce_ms", 250);
+    let log_repeat_window_seconds = try_get_u64(config, "idle.log_repeat_window_seconds", 60);
+    let min_awake_seconds = try_get_u64(config, "idle.min_awake_seconds", 0);
+    let require_activity_first = try_get_bool(config, "idle.require_activity_first", false);
+    let seat = try_get_string(config, "idle.seat");
+    let notify = try_get_bool(config, "idle.notify", false);
 
     // --- Inhibited Apps ---
-    let inhibit_apps: Vec<AppPattern> = match try_get_value(&config, "idle.inhibit_apps") {
+    let inhibit_apps = try_get_patterns(config, "idle.inhibit_apps", &mut warnings);
+
+    // --- Ignored Input Devices ---
+    let ignore_input_devices = try_get_patterns(config, "idle.ignore_input_devices", &mut warnings);
+
+    // --- Raw Input Devices ---
+    let input_devices: Vec<String> = match try_get_value(config, "idle.input_devices") {
         Some(Value::Array(arr)) => arr
             .iter()
             .filter_map(|v| match v {
-                Value::String(s) => parse_app_pattern(s).ok(),
-                Value::Regex(s) => Regex::new(s).ok().map(AppPattern::Regex),
+                Value::String(s) => Some(s.clone()),
                 _ => None,
             })
             .collect(),
         _ => Vec::new(),
     };
 
+    let ignore_input_jitter = try_get_bool(config, "idle.ignore_input_jitter", false);
+    let input_jitter_threshold = match try_get_value(config, "idle.input_jitter_threshold") {
+        Some(Value::Number(n)) if n >= 0.0 => n,
+        _ => 3.0,
+    };
+
+    // --- Remote Media Players ---
+    let remote_media_players: Vec<String> = match try_get_value(config, "idle.remote_media_players") {
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .filter_map(|v| match v {
+                Value::String(s) => Some(s.to_lowercase()),
+                _ => None,
+            })
+            .collect(),
+        _ => DEFAULT_REMOTE_MEDIA_PLAYERS.iter().map(|s| s.to_string()).collect(),
+    };
+
+    let media_inhibit_players = try_get_patterns(config, "idle.media_inhibit_players", &mut warnings);
+    let media_ignore_players = try_get_patterns(config, "idle.media_ignore_players", &mut warnings);
+    let media_require_audio = try_get_bool(config, "idle.media_require_audio", false);
+
     // --- Actions ---
+    let suspend_method = try_get_suspend_method(config);
+    if let Some(raw) = try_get_string(config, "idle.suspend_method")
+        && let Err(e) = raw.parse::<SuspendMethod>()
+    {
+        warnings.push(ConfigWarning::error(format!("idle.suspend_method: {}, using command", e)));
+    }
+    if keep_alive_seconds > 0 && !cfg!(feature = "wlroots_virtual_keyboard") {
+        warnings.push(ConfigWarning::warning("idle.keep_alive_seconds: stasis was built without the wlroots_virtual_keyboard feature, ignored"));
+    }
+    let wake_key = match try_get_value(config, "idle.wake_key") {
+        Some(Value::Number(n)) => WakeKey::parse(&(n as u64).to_string()),
+        Some(Value::String(s)) => WakeKey::parse(&s),
+        _ => None,
+    };
+    if wake_key.is_none() && try_get_value(config, "idle.wake_key").is_some() {
+        warnings.push(ConfigWarning::error("idle.wake_key: expected a key name (shift, ctrl, alt, f13-f24) or an evdev keycode, ignored"));
+    }
+    if wake_key.is_some() && !cfg!(feature = "wlroots_virtual_keyboard") {
+        warnings.push(ConfigWarning::warning("idle.wake_key: stasis was built without the wlroots_virtual_keyboard feature, ignored"));
+    }
+    let on_battery_timeout_scale = match try_get_value(config, "idle.on_battery.timeout_scale") {
+        Some(Value::Number(n)) if n > 0.0 => Some(n),
+        Some(Value::String(s)) => s.parse::<f64>().ok().filter(|n| *n > 0.0),
+        _ => None,
+    };
+    let battery_timeout_scale = match try_get_value(config, "idle.battery_timeout_scale") {
+        Some(Value::Number(n)) if n > 0.0 => Some(n),
+        Some(Value::String(s)) => s.parse::<f64>().ok().filter(|n| *n > 0.0),
+        _ => None,
+    };
+    let low_battery_threshold = try_get_value(config, "idle.on_battery.low_threshold").and_then(|v| {
+        let percent = match v {
+            Value::Number(n) if (0.0..=100.0).contains(&n) => Some(n as u8),
+            Value::String(s) => s.trim_end_matches('%').parse::<u8>().ok().filter(|n| *n <= 100),
+            _ => None,
+        };
+        if percent.is_none() {
+            warnings.push(ConfigWarning::error("idle.on_battery.low_threshold: expected a percentage between 0 and 100, ignored"));
+        }
+        percent
+    });
+    let disable_display_actions_on_ac = try_get_bool(config, "idle.disable_display_actions_on_ac", false)
+        || try_get_bool(config, "idle.on_ac.no_display_actions", false);
+    let follow_de_idle_delay = try_get_string(config, "idle.follow_de_idle_delay").map(|k| normalize_key(&k));
+    // The override must be in place before actions are sorted into AC/battery
+    let force_chassis = match (
+        try_get_bool(config, "idle.force_laptop", false),
+        try_get_bool(config, "idle.force_desktop", false),
+    ) {
+        (true, true) => {
+            warnings.push(ConfigWarning::error("idle.force_laptop and idle.force_desktop are both set, detecting the chassis instead"));
+            None
+        }
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None,
+    };
+    set_chassis_override(force_chassis);
     let laptop = is_laptop();
     let actions = if laptop {
         // Laptop: only AC/Battery
         let mut map = HashMap::new();
-        map.extend(collect_actions(&config, "idle.on_ac", "ac"));
-        map.extend(collect_actions(&config, "idle.on_battery", "battery"));
+        map.extend(collect_actions(config, "idle.on_ac", "ac", &mut warnings));
+        let battery = collect_actions(config, "idle.on_battery", "battery", &mut warnings);
+
+        // A battery block with only a scale derives its actions from the AC ones
+        let has_battery = !battery.is_empty() || map.keys().any(|k| k.starts_with("battery."));
+        if !has_battery && let Some(scale) = on_battery_timeout_scale {
+            let derived: Vec<_> = map
+                .values()
+                .map(|action: &IdleAction| {
+                    let name = action.name.replacen("ac.", "battery.", 1);
+                    let mut derived = action.clone();
+                    derived.name = name.clone();
+                    derived.timeout_seconds = (action.timeout_seconds as f64 * scale).round() as u64;
+                    // Keep instant actions instant, but never turn a timed one into an instant one
+                    if action.timeout_seconds > 0 {
+                        derived.timeout_seconds = derived.timeout_seconds.max(1);
+                    }
+                    (name, derived)
+                })
+                .collect();
+            map.extend(derived);
+        }
+        map.extend(battery);
+        map.extend(collect_actions(config, "idle.on_low_battery", "low_battery", &mut warnings));
         map
     } else {
         // Desktop: load only top-level idle actions that are not AC/Battery blocks
-        collect_actions(&config, "idle", "desktop")
+        collect_actions(config, "idle", "desktop", &mut warnings)
     };
 
+    // --- Manual Triggers ---
+    let triggers = collect_triggers(config, "idle.triggers", &mut warnings);
+
+    // Standalone commands get the same missing-binary check as actions
+    let standalone = [
+        ("idle.resume_command", &resume_command),
+        ("idle.pre_suspend_command", &pre_suspend_command),
+        ("idle.pre_resume_command", &pre_resume_command),
+        ("idle.warn_command", &warn_command),
+        ("idle.pre_action_command", &pre_action_command),
+        ("idle.inhibit_warn_command", &inhibit_warn_command),
+    ];
+    for (what, cmd) in standalone {
+        if let Some(cmd) = cmd {
+            check_program(what, crate::utils::command_program(cmd), &mut warnings);
+        }
+    }
+    for (name, commands) in &triggers {
+        for cmd in commands {
+            check_program(&format!("idle.triggers.{}", name), crate::utils::command_program(cmd), &mut warnings);
+        }
+    }
+
+    // --- Schedule ---
+    let schedule = collect_schedule(config, "idle.schedule", &mut warnings);
+
     // --- Logging ---
     log_message("Parsed Config:");
     log_message(&format!("  resume_command = {:?}", resume_command));
     log_message(&format!("  pre_suspend_command = {:?}", pre_suspend_command));
+    log_message(&format!("  pre_resume_command = {:?}", pre_resume_command));
+    log_message(&format!("  resume_delay_ms = {}", resume_delay_ms));
+    log_message(&format!("  resume_wait_for_activity = {}", resume_wait_for_activity));
+    log_message(&format!("  lock_before_suspend = {}", lock_before_suspend));
+    log_message(&format!("  suspend_method = {}", suspend_method));
+    log_message(&format!("  suspend_interactive = {}", suspend_interactive));
+    log_message(&format!("  auto_reload = {:?}", auto_reload));
     log_message(&format!("  monitor_media = {:?}", monitor_media));
+    log_message(&format!("  ignore_remote_media = {:?}", ignore_remote_media));
+    log_message(&format!("  remote_media_players = {:?}", remote_media_players));
+    log_message(&format!(
+        "  media_inhibit_players = [{}]",
+        media_inhibit_players.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+    ));
+    log_message(&format!(
+        "  media_ignore_players = [{}]",
+        media_ignore_players.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+    ));
+    log_message(&format!("  media_require_audio = {}", media_require_audio));
+    log_message(&format!("  media_poll_seconds = {}s", media_poll_seconds));
     log_message(&format!("  respect_idle_inhibitors = {:?}", respect_idle_inhibitors));
+    log_message(&format!("  screensaver_inhibit = {:?}", screensaver_inhibit));
+    log_message(&format!("  inhibit_lockfile = {:?}", inhibit_lockfile));
     log_message(&format!(
         "  inhibit_apps = [{}]",
         inhibit_apps
@@ -340,21 +1434,198 @@ pub fn load_config(path: &str) -> Result<IdleConfig> {
             .collect::<Vec<_>>()
             .join(", ")
     ));
+    log_message(&format!("  app_poll_seconds = {}s", app_poll_seconds));
+    log_message(&format!("  inhibit_on_fullscreen = {:?}", inhibit_on_fullscreen));
+    log_message(&format!("  min_idle_duration = {}s", min_idle_duration));
+    log_message(&format!("  warn_before_seconds = {}s", warn_before_seconds));
+    log_message(&format!("  warn_command = {:?}", warn_command));
+    log_message(&format!("  pre_action_command = {:?}", pre_action_command));
+    log_message(&format!("  pre_action_timeout_seconds = {}s", pre_action_timeout_seconds));
+    log_message(&format!("  inhibit_warn_after = {}s", inhibit_warn_after));
+    log_message(&format!("  inhibit_warn_command = {:?}", inhibit_warn_command));
+    log_message(&format!("  keep_alive_seconds = {}s", keep_alive_seconds));
+    log_message(&format!("  wake_key = {:?}", wake_key));
+    log_message(&format!("  max_concurrent_actions = {}", max_concurrent_actions));
+    log_message(&format!("  command_log_lines = {}", command_log_lines));
+    log_message(&format!("  command_timeout_seconds = {:?}", command_timeout_seconds));
+    log_message(&format!("  log_file = {:?}", log_file));
+    log_message(&format!("  reload_debounce_ms = {}", reload_debounce_ms));
+    log_message(&format!("  log_repeat_window_seconds = {}s", log_repeat_window_seconds));
+    log_message(&format!("  min_awake_seconds = {}s", min_awake_seconds));
+    log_message(&format!("  require_activity_first = {}", require_activity_first));
+    log_message(&format!("  seat = {:?}", seat));
+    log_message(&format!("  notify = {}", notify));
+    log_message(&format!("  input_devices = {:?}", input_devices));
+    log_message(&format!(
+        "  ignore_input_devices = [{}]",
+        ignore_input_devices.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+    ));
+    log_message(&format!("  ignore_input_jitter = {} (threshold {}px)", ignore_input_jitter, input_jitter_threshold));
+    log_message(&format!("  notification_margin_ms = {}", notification_margin_ms));
     log_message("  actions:");
     for (key, action) in &actions {
         log_message(&format!(
-            "    {}: timeout={}s, kind={:?}, command=\"{}\"",
-            key, action.timeout_seconds, action.kind, action.command
+            "    {}: timeout={}s, kind={:?}, command=\"{}\", enabled={}, dim_to={:?}, fade_seconds={}",
+            key, action.timeout_seconds, action.kind, action.command, action.enabled,
+            action.dim_to, action.fade_seconds
+        ));
+    }
+    log_message(&format!("  on_battery.timeout_scale = {:?}", on_battery_timeout_scale));
+    log_message(&format!("  low_battery_threshold = {:?}", low_battery_threshold));
+    log_message(&format!("  battery_timeout_scale = {:?}", battery_timeout_scale));
+    log_message(&format!("  disable_display_actions_on_ac = {:?}", disable_display_actions_on_ac));
+    log_message(&format!("  force_chassis = {:?}", force_chassis));
+    log_message(&format!("  follow_de_idle_delay = {:?}", follow_de_idle_delay));
+    log_message("  schedule:");
+    for entry in &schedule {
+        log_message(&format!(
+            "    {}: days={:?}, hours={:?}, multiplier={}",
+            entry.name, entry.days, entry.window, entry.multiplier
         ));
     }
+    log_message("  triggers:");
+    for (name, commands) in &triggers {
+        log_message(&format!("    {}: commands={:?}", name, commands));
+    }
+
+    for warning in &warnings {
+        match warning.severity {
+            Severity::Warning => log_warn_message(&format!("Config {}", warning)),
+            Severity::Error => log_error_message(&format!("Config {}", warning)),
+        }
+    }
 
     Ok(IdleConfig {
         actions,
         resume_command,
         pre_suspend_command,
+        pre_resume_command,
+        resume_delay_ms,
+        resume_wait_for_activity,
+        lock_before_suspend,
+        suspend_method,
+        suspend_interactive,
+        auto_reload,
         monitor_media,
+        ignore_remote_media,
+        remote_media_players,
+        media_inhibit_players,
+        media_ignore_players,
+        media_require_audio,
+        media_poll_seconds,
         respect_idle_inhibitors,
+        screensaver_inhibit,
+        inhibit_lockfile,
         inhibit_apps,
+        app_poll_seconds,
+        inhibit_on_fullscreen,
+        min_idle_duration,
+        warn_before_seconds,
+        warn_command,
+        pre_action_command,
+        pre_action_timeout_seconds,
+        inhibit_warn_after,
+        inhibit_warn_command,
+        keep_alive_seconds,
+        wake_key,
+        input_devices,
+        ignore_input_jitter,
+        input_jitter_threshold,
+        ignore_input_devices,
+        notification_margin_ms,
+        max_concurrent_actions,
+        command_log_lines,
+        command_timeout_seconds,
+        log_file,
+        reload_debounce_ms,
+        log_repeat_window_seconds,
+        min_awake_seconds,
+        require_activity_first,
+        seat,
+        notify,
+        on_battery_timeout_scale,
+        low_battery_threshold,
+        battery_timeout_scale,
+        disable_display_actions_on_ac,
+        force_chassis,
+        follow_de_idle_delay,
+        triggers,
+        schedule,
+        warnings,
     })
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn on_list_registers_an_action_for_each_power_source() {
+        let source = r#"
+idle:
+  on_ac:
+    lock_screen:
+      timeout 300
+      command "true"
+      on ["ac", "battery"]
+    end
+    dpms:
+      timeout 600
+      command "true"
+    end
+  end
+end
+"#;
+        let config = RuneConfig::from_str(source).unwrap();
+        let mut warnings = Vec::new();
+        let actions = collect_actions(&config, "idle.on_ac", "ac", &mut warnings);
+
+        let mut names: Vec<_> = actions.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["ac.dpms", "ac.lock-screen", "battery.lock-screen"]);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn example_config_parses() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/stasis.rune");
+        let cfg = load_config(Path::new(path)).unwrap();
+        assert!(!cfg.actions.is_empty());
+        // The commands' programs may be missing on the machine running the tests
+        let problems: Vec<_> = cfg.warnings.iter().filter(|w| w.is_error()).collect();
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn log_file_is_read_ahead_of_the_full_parse() {
+        let path = std::env::temp_dir().join(format!("stasis-log-file-{}.rune", std::process::id()));
+        std::fs::write(&path, "idle:\n  log_file \"/var/tmp/stasis.log\"\n  bogus_key 1\nend\n").unwrap();
+        let log_file = read_log_file(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(log_file, Some(PathBuf::from("/var/tmp/stasis.log")));
+    }
+
+    #[test]
+    fn invalid_regex_warns_under_its_own_key() {
+        let cfg = load_config_str("idle:\n  force_desktop true\n  media_ignore_players [\"spot(ify\"]\nend\n").unwrap();
+        let warning = cfg.warnings.iter().find(|w| w.message.contains("invalid regex")).unwrap();
+        assert!(warning.is_error());
+        assert!(warning.message.starts_with("idle.media_ignore_players: "));
+        assert!(matches!(cfg.media_ignore_players.as_slice(), [AppPattern::Literal(s)] if s == "spot(ify"));
+    }
+
+    #[test]
+    fn expand_env_fills_in_xdg_config_home() {
+        let lookup = |name: &str| (name == "XDG_CONFIG_HOME").then(|| "/home/user/.config".to_string());
+        let mut warnings = Vec::new();
+        assert_eq!(expand_vars("${XDG_CONFIG_HOME}/stasis/lock.sh", lookup, &mut warnings), "/home/user/.config/stasis/lock.sh");
+        assert_eq!(expand_vars("sh $XDG_CONFIG_HOME/hook", lookup, &mut warnings), "sh /home/user/.config/hook");
+        assert!(warnings.is_empty());
+
+        // Unset variables are left as written and reported
+        assert_eq!(expand_vars("$STASIS_UNSET/x", lookup, &mut warnings), "$STASIS_UNSET/x");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].is_error() && warnings[0].message.contains("$STASIS_UNSET"));
+    }
+}
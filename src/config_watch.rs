@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::ipc::ReloadHandle;
+use crate::log::{log_error_message, log_message};
+
+/// Quiet period after the last change before reloading, editors often write several times
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watch the config file and reload on change. Watches the parent directory
+/// so editors that save by renaming over the file still work. Aborting the
+/// task drops the watcher and stops watching.
+pub fn spawn_config_watcher(
+    cfg_path: PathBuf,
+    reloader: ReloadHandle,
+) -> Option<JoinHandle<()>> {
+    let (Some(dir), Some(file_name)) = (cfg_path.parent(), cfg_path.file_name()) else {
+        log_error_message(&format!("Cannot watch config path {}", cfg_path.display()));
+        return None;
+    };
+    let file_name = file_name.to_os_string();
+
+    // notify calls back on its own thread, hand the events over to the task
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .and_then(|mut watcher| watcher.watch(dir, RecursiveMode::NonRecursive).map(|_| watcher));
+    let watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            log_error_message(&format!("Failed to watch {} for changes: {}", dir.display(), e));
+            return None;
+        }
+    };
+    log_message(&format!("Auto-reload enabled, watching {}", cfg_path.display()));
+
+    Some(tokio::spawn(async move {
+        // Owned by the task so it lives exactly as long as the watch
+        let _watcher = watcher;
+
+        loop {
+            match rx.recv().await {
+                Some(Ok(event)) if touches(&event, &file_name) => {}
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    log_error_message(&format!("Config watcher error: {}", e));
+                    continue;
+                }
+                None => return,
+            }
+
+            // Swallow the burst of events from a single save
+            while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+
+            log_message("Config file changed, reloading");
+            match crate::config::load_config(&cfg_path) {
+                Ok(new_cfg) => reloader.request(new_cfg),
+                Err(e) => log_error_message(&format!("Auto-reload failed, keeping previous config: {:#}", e)),
+            }
+        }
+    }))
+}
+
+/// Whether `event` wrote, created or renamed `file_name`. Metadata-only
+/// changes and reads are ignored.
+fn touches(event: &Event, file_name: &std::ffi::OsStr) -> bool {
+    matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+        && !matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)))
+        && event.paths.iter().any(|p| p.file_name() == Some(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn saving_the_config_requests_a_reload() {
+        let dir = std::env::temp_dir().join(format!("stasis-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stasis.rune");
+        let source = "idle:\n  force_desktop true\n  notify_me:\n    timeout 60\n    command \"true\"\n  end\nend\n";
+        std::fs::write(&path, source).unwrap();
+
+        let (reloader, mut requests) = ReloadHandle::channel();
+        let watcher = spawn_config_watcher(path.clone(), reloader).unwrap();
+        std::fs::write(dir.join("other.rune"), "").unwrap();
+        std::fs::write(&path, source.replace("timeout 60", "timeout 90")).unwrap();
+
+        let (cfg, _) = tokio::time::timeout(Duration::from_secs(5), requests.recv()).await.unwrap().unwrap();
+        assert_eq!(cfg.actions.values().next().unwrap().timeout_seconds, 90);
+
+        // Aborting the task drops the watcher along with the reload handle
+        watcher.abort();
+        let _ = watcher.await;
+        assert!(requests.recv().await.is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use tokio::sync::{broadcast::error::RecvError, Mutex};
+use zbus::{connection, interface, object_server::SignalEmitter};
+
+use crate::idle_timer::{IdleTimer, StateEvent};
+use crate::log::{log_error_message, log_message};
+
+const BUS_NAME: &str = "org.stasis.Manager";
+const OBJECT_PATH: &str = "/org/stasis/Manager";
+
+/// `org.stasis.Manager`: signals for Stasis state transitions, so bars and
+/// scripts can subscribe instead of polling `stasis info`
+struct Manager;
+
+#[interface(name = "org.stasis.Manager")]
+impl Manager {
+    /// The first idle action of an idle period fired
+    #[zbus(signal)]
+    async fn idle(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    /// Activity returned the session from idle
+    #[zbus(signal)]
+    async fn active(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    /// The pre-suspend command is about to run
+    #[zbus(signal)]
+    async fn pre_suspend(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    /// Manual inhibit (`stasis pause`/`toggle-inhibit`) was switched on or off
+    #[zbus(signal)]
+    async fn manual_inhibit_changed(emitter: &SignalEmitter<'_>, inhibited: bool) -> zbus::Result<()>;
+}
+
+/// Serve `org.stasis.Manager` on the session bus and forward IdleTimer transitions as signals
+pub fn spawn_dbus_api(idle_timer: Arc<Mutex<IdleTimer>>) {
+    tokio::spawn(async move {
+        if let Err(e) = serve(idle_timer).await {
+            log_error_message(&format!("D-Bus API failed: {}", e));
+        }
+    });
+}
+
+async fn serve(idle_timer: Arc<Mutex<IdleTimer>>) -> zbus::Result<()> {
+    let mut events = idle_timer.lock().await.subscribe_state();
+
+    let connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Manager)?
+        .build()
+        .await?;
+    let emitter = SignalEmitter::new(&connection, OBJECT_PATH)?;
+    log_message("Serving org.stasis.Manager on the session bus");
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        };
+
+        let result = match event {
+            StateEvent::Idle => Manager::idle(&emitter).await,
+            StateEvent::Active => Manager::active(&emitter).await,
+            StateEvent::PreSuspend => Manager::pre_suspend(&emitter).await,
+            StateEvent::ManualInhibitChanged(inhibited) => Manager::manual_inhibit_changed(&emitter, inhibited).await,
+        };
+        if let Err(e) = result {
+            log_error_message(&format!("Failed to emit D-Bus signal: {}", e));
+        }
+    }
+}
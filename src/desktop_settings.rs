@@ -0,0 +1,83 @@
+use std::{process::Stdio, sync::Arc, time::Duration};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use crate::idle_timer::IdleTimer;
+use crate::log::{log_error_message, log_message};
+
+const IDLE_DELAY_SCHEMA: &str = "org.gnome.desktop.session";
+const IDLE_DELAY_KEY: &str = "idle-delay";
+
+/// Parse gsettings output such as `uint32 300` or, from `monitor`, `idle-delay: uint32 300`
+fn parse_idle_delay(line: &str) -> Option<u64> {
+    line.split_whitespace().last()?.parse().ok()
+}
+
+async fn read_idle_delay() -> Option<u64> {
+    let output = Command::new("gsettings")
+        .args(["get", IDLE_DELAY_SCHEMA, IDLE_DELAY_KEY])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_idle_delay(&String::from_utf8_lossy(&output.stdout))
+}
+
+async fn apply(idle_timer: &Arc<Mutex<IdleTimer>>, seconds: u64) {
+    if seconds == 0 {
+        log_message("Desktop idle-delay is 0 (never), keeping configured timeout");
+        return;
+    }
+    idle_timer.lock().await.set_de_idle_delay(seconds);
+}
+
+/// Follow the desktop environment's idle-delay setting (GNOME gsettings) and
+/// use it as the timeout for the action named by `follow_de_idle_delay`
+pub fn spawn_de_idle_delay_watcher(idle_timer: Arc<Mutex<IdleTimer>>) {
+    tokio::spawn(async move {
+        match read_idle_delay().await {
+            Some(seconds) => {
+                log_message(&format!("Desktop idle-delay is {}s", seconds));
+                apply(&idle_timer, seconds).await;
+            }
+            None => {
+                log_error_message("Could not read desktop idle-delay via gsettings, keeping configured timeout");
+                return;
+            }
+        }
+
+        loop {
+            let child = Command::new("gsettings")
+                .args(["monitor", IDLE_DELAY_SCHEMA, IDLE_DELAY_KEY])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .kill_on_drop(true)
+                .spawn();
+
+            let mut child = match child {
+                Ok(child) => child,
+                Err(e) => {
+                    log_error_message(&format!("Failed to monitor desktop idle-delay: {}", e));
+                    return;
+                }
+            };
+
+            if let Some(stdout) = child.stdout.take() {
+                let mut lines = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Some(seconds) = parse_idle_delay(&line) {
+                        log_message(&format!("Desktop idle-delay changed to {}s", seconds));
+                        apply(&idle_timer, seconds).await;
+                    }
+                }
+            }
+
+            // Monitor exited (e.g. dconf restarted), retry after a pause
+            let _ = child.wait().await;
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    });
+}
@@ -0,0 +1,79 @@
+use std::process::ExitStatus;
+use thiserror::Error;
+
+/// Errors from compositor IPC queries used for app inhibition
+#[derive(Debug, Error)]
+pub enum InhibitError {
+    /// No IPC backend for this desktop, callers should fall back to process scanning
+    #[error("no compositor IPC available for '{0}'")]
+    Unsupported(String),
+
+    /// The compositor's IPC tool could not be spawned (not installed or not running)
+    #[error("failed to run {command}: {source}")]
+    Spawn {
+        command: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The IPC tool ran but reported an error
+    #[error("{command} failed: {stderr}")]
+    CommandFailed {
+        command: &'static str,
+        stderr: String,
+    },
+
+    /// The IPC tool produced output we could not parse
+    #[error("malformed output from {command}: {reason}")]
+    MalformedOutput {
+        command: &'static str,
+        reason: String,
+    },
+}
+
+/// Errors while serving a control socket connection
+#[derive(Debug, Error)]
+pub enum IpcError {
+    #[error("failed to read control command: {0}")]
+    Read(#[source] std::io::Error),
+
+    #[error("failed to send {what}: {source}")]
+    Write {
+        what: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("unknown control command: {0}")]
+    UnknownCommand(String),
+
+    #[error("failed to reload config: {0:#}")]
+    Reload(eyre::Report),
+}
+
+/// Errors from synchronously run commands (e.g. pre-suspend)
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("failed to spawn command: {0}")]
+    Spawn(#[source] std::io::Error),
+
+    #[error("command exited with status: {0}")]
+    Exit(ExitStatus),
+
+    #[error("command timed out after {0}s")]
+    Timeout(u64),
+}
+
+/// Errors from the suspend event listener
+#[derive(Debug, Error)]
+pub enum SuspendError {
+    /// Neither logind nor ConsoleKit owns its name on the system bus
+    #[error("no logind or ConsoleKit on the system bus")]
+    NoBackend,
+
+    #[error(transparent)]
+    DBus(#[from] zbus::Error),
+
+    #[error(transparent)]
+    Fdo(#[from] zbus::fdo::Error),
+}
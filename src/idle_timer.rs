@@ -1,38 +1,250 @@
 use std::{
-    collections::HashSet,
-    sync::Arc,
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 use futures::future::BoxFuture;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, watch, Mutex, Notify, Semaphore};
 use tokio::task::JoinHandle;
 
+use crate::actions::{ActionCommand, ActionRequest, RunLimits};
 use crate::config::{IdleAction, IdleActionKind, IdleConfig};
-use crate::log::{log_error_message, log_message};
-use crate::brightness::{capture_brightness, restore_brightness, BrightnessState};
+use crate::error::CommandError;
+use crate::log::{log_debug_message, log_error_message, log_message, log_trace_message};
+use crate::brightness::{capture_brightness, dim_to, restore_brightness, BrightnessState, DEFAULT_FADE_STEPS};
+
+/// State transitions published to subscribers such as the D-Bus API
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "dbus_api"), allow(dead_code))]
+pub enum StateEvent {
+    /// The first action of an idle episode fired
+    Idle,
+    /// Activity after idle actions fired
+    Active,
+    PreSuspend,
+    ManualInhibitChanged(bool),
+}
+
+/// What last reset the idle timer, shown by `stasis info`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetSource {
+    Keyboard,
+    Pointer,
+    /// A raw evdev device from `input_devices`
+    InputDevice,
+    /// The compositor's idle notification resumed
+    Compositor,
+    /// An app called org.freedesktop.ScreenSaver.SimulateActivity
+    ScreenSaver,
+    ManualResume,
+    AutoResume,
+}
+
+impl std::fmt::Display for ResetSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ResetSource::Keyboard => "keyboard",
+            ResetSource::Pointer => "pointer",
+            ResetSource::InputDevice => "input device",
+            ResetSource::Compositor => "compositor",
+            ResetSource::ScreenSaver => "ScreenSaver SimulateActivity",
+            ResetSource::ManualResume => "manual resume",
+            ResetSource::AutoResume => "automatic resume",
+        };
+        write!(f, "{}", name)
+    }
+}
 
-const MAX_SPAWNED_TASKS: usize = 10;
+/// Automatic sources that inhibit idle. Each keeps its own flag so one
+/// clearing can't lift another that is still active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InhibitSource {
+    /// An `inhibit_apps` app or, with `inhibit_on_fullscreen`, a fullscreen window
+    Apps,
+    Media,
+    ScreenSaver,
+    /// `inhibit_lockfile` exists
+    Lockfile,
+}
+
+/// Tasks waiting for a free slot once `max_concurrent_actions` are running
+const MAX_QUEUED_TASKS: usize = 32;
+
+/// A `pre_action_command` run taken out of `check_idle`, so the idle task can
+/// await it without holding the timer lock
+struct PreActionCheck {
+    action: String,
+    command: String,
+    timeout: Duration,
+    /// `last_activity` when the check was taken
+    activity: Instant,
+}
+
+impl PreActionCheck {
+    /// Run the command, false if it vetoed (nonzero exit or timeout)
+    async fn run(&self) -> bool {
+        let limits = RunLimits { kill_on_drop: true, timeout: Some(self.timeout), kill_on_timeout: true };
+        match crate::actions::run_action_command(&ActionCommand::Shell(self.command.clone()), limits).await {
+            Ok(()) => true,
+            Err(e) => {
+                log_message(&format!("Pre-action command vetoed {}: {}", self.action, e));
+                false
+            }
+        }
+    }
+}
+
+/// The rest of a suspend parked while `lock_before_suspend` waits for the
+/// locker. The idle task waits unlocked, then hands it back.
+struct LockWait {
+    action: IdleAction,
+    /// Requests after the `PreSuspend` that parked it
+    rest: Vec<ActionRequest>,
+    locker: String,
+}
+
+impl LockWait {
+    /// Wait for the locker process to appear, up to `LOCK_WAIT_TIMEOUT`
+    async fn run(&self) {
+        let started = Instant::now();
+        while !crate::actions::is_process_running(&self.locker).await {
+            if started.elapsed() >= LOCK_WAIT_TIMEOUT {
+                log_error_message(&format!(
+                    "Locker did not start within {}s, suspending anyway",
+                    LOCK_WAIT_TIMEOUT.as_secs()
+                ));
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        log_message("Locker is up, continuing with suspend");
+    }
+}
+
+/// A spawned action task. It waits for a slot before running, `started`
+/// tells a running task from a queued one.
+struct ActionTask {
+    kind: Option<IdleActionKind>,
+    /// Aborted on activity, see `is_reversible`
+    reversible: bool,
+    handle: JoinHandle<()>,
+    started: Arc<AtomicBool>,
+}
+
+/// Bounds for the idle task's sleep. The floor keeps overdue but held-back
+/// actions from spinning, the ceiling catches schedule changes.
+const MIN_TICK: Duration = Duration::from_secs(1);
+const MAX_TICK: Duration = Duration::from_secs(60);
+
+/// Quiet period after activity (or a vetoed action) before idle is checked again
+const ACTIVITY_DEBOUNCE: Duration = Duration::from_secs(3);
+
+/// How long `lock_before_suspend` waits for the locker process to appear
+const LOCK_WAIT_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct IdleTimer {
     pub cfg: IdleConfig,
     pub start_time: Instant,
     pub last_activity: Instant,
+    /// Where the last reset came from and when, None until the first one
+    pub last_reset: Option<(ResetSource, Instant)>,
     pub debounce_until: Option<Instant>,
-    pub paused: bool,
+    /// `pre_action_command` run waiting for the idle task, which awaits it unlocked
+    pending_pre_action: Option<PreActionCheck>,
+    /// Suspend waiting on the locker, see `LockWait`
+    pending_lock_wait: Option<LockWait>,
+    /// `last_activity` a passed `pre_action_command` vouches for, used up by the next check
+    pre_action_approved: Option<Instant>,
     pub manually_paused: bool,
+    /// Only timeout-driven actions are paused; manual triggers still run
+    pub auto_paused: bool,
+    /// `stasis presentation on`, kept apart from manual and automatic pauses
+    /// so media or inhibit changes can't end it
+    pub presentation: bool,
+    /// Set by the app inhibitor while an `inhibit_apps` app or fullscreen window is up
+    pub app_inhibited: bool,
+    /// Set by the media monitor while a player is playing
+    pub media_playing: bool,
+    /// Set while an app holds an org.freedesktop.ScreenSaver inhibit cookie
+    pub screensaver_inhibited: bool,
+    /// Set while `inhibit_lockfile` exists
+    pub lockfile_inhibited: bool,
     pub resume_command: Option<String>,
     pub on_ac: bool,
+    /// Set by `stasis set-power`, overrides detection until set back to auto
+    pub forced_power: Option<bool>,
+    /// When the power source last changed, pruned to the past hour
+    power_flips: VecDeque<Instant>,
     actions: Vec<IdleAction>,
     ac_actions: Vec<IdleAction>,
     battery_actions: Vec<IdleAction>,
+    /// `on_low_battery` actions, merged over the battery ones while low
+    low_battery_actions: Vec<IdleAction>,
+    /// Last battery reading in percent, None on desktops
+    pub battery_percent: Option<u8>,
+    low_battery: bool,
     pre_suspend_command: Option<String>,
     is_idle_flags: Vec<bool>,
     compositor_managed: bool,
     active_kinds: HashSet<String>,
+    /// When each action kind last fired, for `cooldown_seconds`
+    last_fired: HashMap<String, Instant>,
+    /// Idle time at which each fired `repeat` action fires again, by action name
+    repeat_at: HashMap<String, Duration>,
+    /// Times each action (by name) and trigger (`trigger.<name>`) has fired since start or the last reload
+    pub fire_counts: HashMap<String, u64>,
     previous_brightness: Option<BrightnessState>,
+    active_schedule: Option<String>,
+    de_idle_delay: Option<u64>,
     suspend_occurred: bool,
-    spawned_tasks: Vec<JoinHandle<()>>,
+    /// Names of actions as they fire, for `stasis wait-idle`
+    action_events: broadcast::Sender<String>,
+    state_events: broadcast::Sender<StateEvent>,
+    /// Marked changed after each config reload, for tasks holding settings of their own
+    reloads: watch::Sender<()>,
+    /// Compositor idle timeout in ms, republished by the idle task so the
+    /// Wayland loop re-arms its notifications whenever it changes
+    compositor_timeout: watch::Sender<u32>,
+    /// Wakes the idle task early, see `rearm`
+    rearm: Arc<Notify>,
+    /// Set on the first activity since startup, for `require_activity_first`
+    had_activity: bool,
+    /// When the system last woke from sleep, for the `min_awake_seconds` guard
+    last_resume: Option<Instant>,
+    /// A suspend was held back by `min_awake_seconds` and already logged
+    suspend_guard_logged: bool,
+    /// `warn_command` already ran this idle period
+    warning_fired: bool,
+    /// Deadline and auto-resume task of a timed `stasis inhibit`
+    inhibit_expiry: Option<(Instant, JoinHandle<()>)>,
+    /// Start of the current continuous inhibition and whether it was reported
+    inhibited_since: Option<Instant>,
+    inhibit_warned: bool,
+    /// Bumped on each active-to-idle transition so logs can group the actions of one episode
+    idle_episode: u64,
+    episode_active: bool,
+    /// Tasks tagged with the kind of action that spawned them, if any,
+    /// including those still waiting for a slot
+    spawned_tasks: Vec<ActionTask>,
+    /// One permit per `max_concurrent_actions`, held by running tasks and sequence steps
+    action_slots: Arc<Semaphore>,
+    sequence_tasks: Vec<JoinHandle<()>>,
     idle_task_handle: Option<JoinHandle<()>>,
+    config_watcher: Option<JoinHandle<()>>,
+    /// MPRIS monitor while `monitor_media` is on, so a reload can stop it
+    media_monitor: Option<JoinHandle<()>>,
+    #[cfg(feature = "wlr_output_power")]
+    output_power: Option<crate::wayland::output_power::OutputPower>,
+    /// Outputs were powered off natively and must be turned back on
+    #[cfg(feature = "wlr_output_power")]
+    outputs_powered_off: bool,
+    #[cfg(feature = "wlroots_virtual_keyboard")]
+    virtual_keyboard: Option<crate::wayland::virtual_keyboard::VirtualKeyboard>,
+    #[cfg(feature = "wlroots_virtual_keyboard")]
+    last_keep_alive: Option<Instant>,
 }
 
 impl IdleTimer {
@@ -42,7 +254,7 @@ impl IdleTimer {
         let default_actions: Vec<_> = cfg
             .actions
             .iter()
-            .filter(|(k, _)| !k.starts_with("ac.") && !k.starts_with("battery."))
+            .filter(|(k, _)| !k.starts_with("ac.") && !k.starts_with("battery.") && !k.starts_with("low_battery."))
             .map(|(_, v)| v.clone())
             .collect();
 
@@ -60,6 +272,13 @@ impl IdleTimer {
             .map(|(_, v)| v.clone())
             .collect();
 
+        let low_battery_actions: Vec<_> = cfg
+            .actions
+            .iter()
+            .filter(|(k, _)| k.starts_with("low_battery."))
+            .map(|(_, v)| v.clone())
+            .collect();
+
         let actions = if !ac_actions.is_empty() || !battery_actions.is_empty() {
             if on_ac { ac_actions.clone() } else { battery_actions.clone() }
         } else {
@@ -68,27 +287,77 @@ impl IdleTimer {
 
         let actions_clone = actions.clone();
         let now = Instant::now();
+        crate::actions::set_command_log_lines(cfg.command_log_lines);
+        crate::actions::set_command_timeout(cfg.command_timeout_seconds.unwrap_or(30));
+        crate::actions::set_suspend_method(cfg.suspend_method);
+        crate::log::set_log_file(cfg.log_file.as_ref().map(std::path::PathBuf::from));
+        crate::log::set_error_repeat_window(cfg.log_repeat_window_seconds);
         
         let timer = Self {
             cfg: cfg.clone(),
             start_time: now,
             last_activity: now,
+            last_reset: None,
             debounce_until: None,
+            pending_pre_action: None,
+            pending_lock_wait: None,
+            pre_action_approved: None,
             actions,
             ac_actions,
             battery_actions,
+            low_battery_actions,
+            battery_percent: None,
+            low_battery: false,
             resume_command: cfg.resume_command.clone(),
             pre_suspend_command: cfg.pre_suspend_command.clone(),
             is_idle_flags: vec![false; actions_clone.len()],
             compositor_managed: false,
             active_kinds: HashSet::new(),
+            last_fired: HashMap::new(),
+            repeat_at: HashMap::new(),
+            fire_counts: HashMap::new(),
             previous_brightness: None,
+            action_events: broadcast::channel(16).0,
+            state_events: broadcast::channel(16).0,
+            reloads: watch::channel(()).0,
+            compositor_timeout: watch::channel(0).0,
+            rearm: Arc::new(Notify::new()),
+            had_activity: false,
+            last_resume: None,
+            suspend_guard_logged: false,
+            active_schedule: None,
+            de_idle_delay: None,
             on_ac,
-            paused: false,
+            forced_power: None,
+            power_flips: VecDeque::new(),
+            app_inhibited: false,
             manually_paused: false,
+            auto_paused: false,
+            presentation: false,
+            media_playing: false,
+            screensaver_inhibited: false,
+            lockfile_inhibited: false,
             suspend_occurred: false,
+            warning_fired: false,
+            inhibit_expiry: None,
+            inhibited_since: None,
+            inhibit_warned: false,
+            idle_episode: 0,
+            episode_active: false,
             spawned_tasks: Vec::new(),
+            action_slots: Arc::new(Semaphore::new(cfg.max_concurrent_actions)),
+            sequence_tasks: Vec::new(),
             idle_task_handle: None,
+            config_watcher: None,
+            media_monitor: None,
+            #[cfg(feature = "wlr_output_power")]
+            output_power: None,
+            #[cfg(feature = "wlr_output_power")]
+            outputs_powered_off: false,
+            #[cfg(feature = "wlroots_virtual_keyboard")]
+            virtual_keyboard: None,
+            #[cfg(feature = "wlroots_virtual_keyboard")]
+            last_keep_alive: None,
         };
 
         timer
@@ -112,7 +381,11 @@ impl IdleTimer {
         Box::pin(async move {
             let mut instant_actions = Vec::new();
             for (i, action) in self.actions.iter().enumerate() {
-                if action.timeout_seconds == 0 && !self.is_idle_flags[i] {
+                if action.enabled
+                    && action.timeout_seconds == 0
+                    && !self.is_idle_flags[i]
+                    && !self.is_display_suppressed(action)
+                {
                     instant_actions.push((i, action.clone()));
                 }
             }
@@ -121,54 +394,126 @@ impl IdleTimer {
                 self.is_idle_flags[i] = true;
                 self.active_kinds.insert(action.kind.to_string());
 
-                 log_message(&format!(
+                // Startup and power switches aren't idle, only the timeout path begins an episode
+                log_action(&action, &format!(
                     "Instant action triggered: kind={} command=\"{}\"",
                     action.kind, action.command
                 ));
 
-                if action.kind == IdleActionKind::Brightness && self.previous_brightness.is_none() {
-                    if let Some(state) = capture_brightness() {
-                        self.previous_brightness = Some(state.clone());
-                    } else {
-                        log_error_message("Could not capture current brightness");
-                    }
-                }
-
+                // No brightness capture: an instant action sets the level for this
+                // power source, activity must not "restore" it away
                 let requests = crate::actions::prepare_action(&action).await;
-                for req in requests {
-                    match req {
-                        crate::actions::ActionRequest::PreSuspend => {
-                            self.trigger_pre_suspend(false, false).await;
-                        }
-                        crate::actions::ActionRequest::RunCommand(cmd) => {
-                            let cmd_clone = cmd.clone();
-                            self.spawn_task_limited(async move {
-                                if let Err(e) = crate::actions::run_command_silent(&cmd_clone).await {
-                                    log_error_message(&format!("Failed to run command '{}': {}", cmd_clone, e));
-                                }
-                            });
-                        }
-                        crate::actions::ActionRequest::Skip(_) => {}
-                    }
-                }
+                self.dispatch_requests(&action, requests).await;
             }
         })
     }
 
+    /// Refuse to suspend until the system has been awake `min_awake_seconds`,
+    /// counted from startup or the last resume, so a wake can't loop straight back
+    fn suspend_held_back(&mut self) -> bool {
+        let min_awake = Duration::from_secs(self.cfg.min_awake_seconds);
+        let awake = self.last_resume.unwrap_or(self.start_time).elapsed();
+        if awake >= min_awake {
+            self.suspend_guard_logged = false;
+            return false;
+        }
+
+        if !self.suspend_guard_logged {
+            self.suspend_guard_logged = true;
+            log_message(&format!(
+                "Suspend held back: awake {}s, min_awake_seconds is {}s",
+                awake.as_secs(),
+                min_awake.as_secs()
+            ));
+        }
+        true
+    }
+
+    /// Receive the name of every action that fires from now on
+    pub fn subscribe_actions(&self) -> broadcast::Receiver<String> {
+        self.action_events.subscribe()
+    }
+
+    /// Watch for config reloads
+    pub fn subscribe_reloads(&self) -> watch::Receiver<()> {
+        self.reloads.subscribe()
+    }
+
+    /// Watch the compositor idle timeout, see `compositor_timeout_ms`
+    pub fn subscribe_compositor_timeout(&self) -> watch::Receiver<u32> {
+        self.compositor_timeout.subscribe()
+    }
+
+    /// Receive idle/active/pre-suspend/manual inhibit transitions from now on
+    pub fn subscribe_state(&self) -> broadcast::Receiver<StateEvent> {
+        self.state_events.subscribe()
+    }
+
+    /// Record a wake from sleep, starting the `min_awake_seconds` window
+    pub fn mark_resumed(&mut self) {
+        self.last_resume = Some(Instant::now());
+        self.suspend_guard_logged = false;
+        self.send_wake_key();
+    }
+
+    /// Whether any timed action has fired since the last activity. Instant
+    /// actions don't count, they stay applied across activity.
+    fn is_idle(&self) -> bool {
+        self.actions
+            .iter()
+            .zip(self.is_idle_flags.iter())
+            .any(|(action, &fired)| fired && action.timeout_seconds > 0)
+    }
+
+    /// Clear fired flags on activity. Instant actions keep theirs so they only
+    /// re-run on a real state change (power source switch, reload, resume from suspend).
+    fn clear_idle_flags(&mut self) {
+        self.repeat_at.clear();
+        for (action, fired) in self.actions.iter().zip(self.is_idle_flags.iter_mut()) {
+            if action.timeout_seconds > 0 {
+                *fired = false;
+            }
+        }
+    }
+
     pub fn is_manually_inhibited(&self) -> bool {
         self.manually_paused
     }
 
+    /// Time left before a timed manual inhibit resumes on its own
+    pub fn inhibit_remaining(&self) -> Option<Duration> {
+        self.inhibit_expiry
+            .as_ref()
+            .map(|(until, _)| until.saturating_duration_since(Instant::now()))
+    }
+
+    /// Register the task that ends a timed manual inhibit, replacing any earlier one
+    pub fn set_inhibit_expiry(&mut self, duration: Duration, task: JoinHandle<()>) {
+        self.cancel_inhibit_expiry();
+        self.inhibit_expiry = Some((Instant::now() + duration, task));
+    }
+
+    pub fn cancel_inhibit_expiry(&mut self) {
+        if let Some((_, task)) = self.inhibit_expiry.take() {
+            task.abort();
+        }
+    }
+
     pub async fn set_manual_inhibit(&mut self, inhibit: bool) {
         if inhibit {
-            self.pause(true);
+            self.pause();
         } else {
-            self.resume(true);
+            self.resume();
         }
     }
 
     pub async fn check_idle(&mut self) {
-        if self.paused {
+        if self.presentation || self.auto_inhibited() || self.auto_paused {
+            return;
+        }
+
+        // A machine that boots idle shouldn't act before anyone has used it
+        if self.cfg.require_activity_first && !self.had_activity {
             return;
         }
 
@@ -182,43 +527,99 @@ impl IdleTimer {
         }
 
         let elapsed = self.elapsed_idle();
+        // Idle must be sustained this long past an action's timeout before it fires
+        let min_idle = Duration::from_secs(self.cfg.min_idle_duration);
+
+        // Scale timeouts by the schedule entry matching the current local time
+        let multiplier = self.schedule_multiplier() * self.power_multiplier();
+
+        if !self.warning_fired
+            && let Some(warn_at) = self.warning_threshold(multiplier)
+            && elapsed >= warn_at
+        {
+            self.warning_fired = true;
+            log_message("Idle warning threshold reached");
+            if let Some(cmd) = self.cfg.warn_command.clone() {
+                self.spawn_task_limited(async move {
+                    if let Err(e) = crate::actions::run_command_silent(&cmd).await {
+                        log_error_message(&format!("Warn command failed: {}", e));
+                    }
+                });
+            }
+            if self.cfg.notify {
+                crate::notify::spawn_notification(
+                    "Going idle".to_string(),
+                    format!("Idle actions start in {}s", self.cfg.warn_before_seconds),
+                    crate::notify::Urgency::Normal,
+                );
+            }
+        }
+
+        // `pre_action_command` runs at most once per check, before the first action due
+        let mut vetted = self.cfg.pre_action_command.is_none()
+            || self.pre_action_approved.take() == Some(self.last_activity);
 
         for i in 0..self.actions.len() {
             let action = &self.actions[i];
             let key = action.kind.to_string();
+            let period = Duration::from_secs(action.timeout_seconds).mul_f64(multiplier);
 
-            if action.timeout_seconds == 0 || self.is_idle_flags[i] || self.active_kinds.contains(&key)
+            // A fired `repeat` action comes back every `timeout` while idle continues
+            let repeat_at = if self.is_idle_flags[i] && action.repeat {
+                self.repeat_at.get(&action.name).copied()
+            } else {
+                None
+            };
+
+            if !action.enabled
+                || self.is_display_suppressed(action)
+                || action.timeout_seconds == 0
+                || (repeat_at.is_none() && (self.is_idle_flags[i] || self.active_kinds.contains(&key)))
+                || self.last_fired.get(&key).is_some_and(|t| t.elapsed() < Duration::from_secs(action.cooldown_seconds))
             {
                 continue;
             }
 
-            if elapsed >= Duration::from_secs(action.timeout_seconds) {
+            if elapsed >= repeat_at.unwrap_or(period + min_idle) {
+                if action.kind == IdleActionKind::Suspend && self.suspend_held_back() {
+                    continue;
+                }
+                if !vetted {
+                    // Hand the command to the idle task, it checks again once it passes
+                    self.pending_pre_action = self.cfg.pre_action_command.clone().map(|command| PreActionCheck {
+                        action: self.actions[i].name.clone(),
+                        command,
+                        timeout: Duration::from_secs(self.cfg.pre_action_timeout_seconds),
+                        activity: self.last_activity,
+                    });
+                    return;
+                }
+                vetted = true;
                 self.is_idle_flags[i] = true;
                 self.active_kinds.insert(key.clone());
+                self.last_fired.insert(key.clone(), Instant::now());
+                let episode = self.begin_episode();
+                let action = self.actions[i].clone();
+                if action.repeat {
+                    self.repeat_at.insert(action.name.clone(), elapsed + period);
+                }
+                log_action(&action, &format!(
+                    "Idle episode #{}: action fired: {} kind={} after {}s idle",
+                    episode, action.name, action.kind, elapsed.as_secs()
+                ));
+                // No receivers is the normal case
+                let _ = self.action_events.send(action.name.clone());
 
                 if action.kind == IdleActionKind::Brightness && self.previous_brightness.is_none() {
-                    if let Some(state) = capture_brightness() {
-                        self.previous_brightness = Some(state.clone());
+                    if let Some(mut state) = capture_brightness() {
+                        state.fade = action.dim_to.map(|_| Duration::from_secs(action.fade_seconds));
+                        state.restore_command = action.restore_command.clone();
+                        self.previous_brightness = Some(state);
                     }
                 }
 
-                let requests = crate::actions::prepare_action(action).await;
-                for req in requests {
-                    match req {
-                        crate::actions::ActionRequest::PreSuspend => {
-                            self.trigger_pre_suspend(false, false).await;
-                        }
-                        crate::actions::ActionRequest::RunCommand(cmd) => {
-                            let cmd_clone = cmd.clone();
-                            self.spawn_task_limited(async move {
-                                if let Err(e) = crate::actions::run_command_silent(&cmd_clone).await {
-                                    log_error_message(&format!("Failed to run command '{}': {}", cmd_clone, e));
-                                }
-                            });
-                        }
-                        crate::actions::ActionRequest::Skip(_) => {}
-                    }
-                }
+                let requests = crate::actions::prepare_action(&action).await;
+                self.dispatch_requests(&action, requests).await;
 
             }
         }
@@ -226,34 +627,310 @@ impl IdleTimer {
         self.cleanup_tasks();
     }
 
-    pub fn reset(&mut self) {
+    /// Apply the outcome of a `pre_action_command` run. Activity while it ran
+    /// voids the result, a veto holds actions back for another debounce.
+    fn finish_pre_action(&mut self, check: PreActionCheck, allowed: bool) {
+        if self.last_activity != check.activity {
+            log_debug_message("Activity while pre_action_command ran, result dropped");
+        } else if allowed {
+            self.pre_action_approved = Some(check.activity);
+        } else {
+            self.debounce_until = Some(Instant::now() + ACTIVITY_DEBOUNCE);
+        }
+    }
+
+    /// Use the desktop environment's idle-delay as the timeout of the
+    /// action named by `follow_de_idle_delay`
+    pub fn set_de_idle_delay(&mut self, seconds: u64) {
+        self.de_idle_delay = Some(seconds);
+        self.apply_de_idle_delay();
+        self.rearm();
+    }
+
+    fn apply_de_idle_delay(&mut self) {
+        let (Some(target), Some(seconds)) = (&self.cfg.follow_de_idle_delay, self.de_idle_delay) else {
+            return;
+        };
+        let suffix = format!(".{}", target);
+
+        let mut updated = false;
+        for action in self
+            .actions
+            .iter_mut()
+            .chain(self.ac_actions.iter_mut())
+            .chain(self.battery_actions.iter_mut())
+            .chain(self.cfg.actions.values_mut())
+            .filter(|a| a.name.ends_with(&suffix))
+        {
+            action.timeout_seconds = seconds;
+            updated = true;
+        }
+
+        if updated {
+            log_message(&format!("Action '{}' now follows desktop idle-delay: {}s", target, seconds));
+        } else {
+            log_error_message(&format!("follow_de_idle_delay: no action named '{}'", target));
+        }
+    }
+
+    /// What is currently keeping idle actions from running, if anything
+    fn inhibit_source(&self) -> Option<&'static str> {
+        if self.presentation {
+            Some("presentation mode")
+        } else if self.manually_paused {
+            Some("manual pause")
+        } else if self.auto_paused {
+            Some("pause-auto")
+        } else if self.media_playing {
+            Some("media playback")
+        } else if self.screensaver_inhibited {
+            Some("ScreenSaver inhibitor")
+        } else if self.lockfile_inhibited {
+            Some("inhibit lockfile")
+        } else if self.app_inhibited {
+            Some("inhibit_apps")
+        } else {
+            None
+        }
+    }
+
+    /// Run `inhibit_warn_command` once when idle has been inhibited for
+    /// `inhibit_warn_after`, resetting when the inhibition clears
+    pub fn check_inhibit_duration(&mut self) {
+        let Some(source) = self.inhibit_source() else {
+            self.inhibited_since = None;
+            self.inhibit_warned = false;
+            return;
+        };
+
+        let since = *self.inhibited_since.get_or_insert_with(Instant::now);
+        let limit = self.cfg.inhibit_warn_after;
+        if limit == 0 || self.inhibit_warned || since.elapsed() < Duration::from_secs(limit) {
+            return;
+        }
+        self.inhibit_warned = true;
+
+        let duration = crate::utils::format_duration(since.elapsed());
+        log_message(&format!("Idle has been inhibited for {} by {}", duration, source));
+
+        if let Some(cmd) = &self.cfg.inhibit_warn_command {
+            let cmd = cmd.replace("{source}", source).replace("{duration}", &duration);
+            self.spawn_task_limited(async move {
+                if let Err(e) = crate::actions::run_command_silent(&cmd).await {
+                    log_error_message(&format!("Inhibit warn command failed: {}", e));
+                }
+            });
+        }
+    }
+
+    /// Current episode id, starting a new one on the first action after activity
+    fn begin_episode(&mut self) -> u64 {
+        if !self.episode_active {
+            self.episode_active = true;
+            self.idle_episode += 1;
+            let _ = self.state_events.send(StateEvent::Idle);
+        }
+        self.idle_episode
+    }
+
+    /// Id of the ongoing idle episode, None while the user is active
+    pub fn idle_episode(&self) -> Option<u64> {
+        self.episode_active.then_some(self.idle_episode)
+    }
+
+    /// Idle time at which `warn_command` should run: `warn_before_seconds`
+    /// ahead of the first timed action still to fire
+    fn warning_threshold(&self, multiplier: f64) -> Option<Duration> {
+        if self.cfg.warn_before_seconds == 0 || (self.cfg.warn_command.is_none() && !self.cfg.notify) {
+            return None;
+        }
+
+        let first = self
+            .actions
+            .iter()
+            .zip(self.is_idle_flags.iter())
+            .filter(|(a, fired)| !**fired && a.enabled && a.timeout_seconds > 0 && !self.is_display_suppressed(a))
+            .map(|(a, _)| Duration::from_secs(a.timeout_seconds).mul_f64(multiplier))
+            .min()?;
+
+        let fire_at = first + Duration::from_secs(self.cfg.min_idle_duration);
+        Some(fire_at.saturating_sub(Duration::from_secs(self.cfg.warn_before_seconds)))
+    }
+
+    /// Pending idle warning for `stasis info`, None when no warning is configured
+    pub fn warning_status(&self) -> Option<String> {
+        if self.cfg.warn_before_seconds == 0 || (self.cfg.warn_command.is_none() && !self.cfg.notify) {
+            return None;
+        }
+        if self.warning_fired {
+            return Some("fired".to_string());
+        }
+
+        match self.warning_threshold(self.current_multiplier()) {
+            Some(at) => Some(format!("in {}s", at.saturating_sub(self.elapsed_idle()).as_secs())),
+            None => Some("-".to_string()),
+        }
+    }
+
+    /// The timed action that fires next if idle continues, and how long until
+    /// it does. None while paused or when nothing is left to fire.
+    pub fn next_action(&self) -> Option<(String, Duration)> {
+        if self.inhibit_source().is_some() {
+            return None;
+        }
+
+        let multiplier = self.current_multiplier();
+        let min_idle = Duration::from_secs(self.cfg.min_idle_duration);
+        let elapsed = self.elapsed_idle();
+
+        let (action, fire_at) = self
+            .actions
+            .iter()
+            .zip(self.is_idle_flags.iter())
+            .filter(|(a, _)| a.enabled && a.timeout_seconds > 0 && !self.is_display_suppressed(a))
+            .filter_map(|(a, &fired)| {
+                if !fired {
+                    Some((a, Duration::from_secs(a.timeout_seconds).mul_f64(multiplier) + min_idle))
+                } else if a.repeat {
+                    self.repeat_at.get(&a.name).map(|&at| (a, at))
+                } else {
+                    None
+                }
+            })
+            .map(|(a, at)| {
+                // A due action still in its `cooldown_seconds` waits for the cooldown to run out
+                let cooldown = self
+                    .last_fired
+                    .get(&a.kind.to_string())
+                    .map_or(Duration::ZERO, |t| Duration::from_secs(a.cooldown_seconds).saturating_sub(t.elapsed()));
+                (a, at.max(elapsed + cooldown))
+            })
+            .min_by_key(|(_, at)| *at)?;
+
+        Some((action.name.clone(), fire_at.saturating_sub(elapsed)))
+    }
+
+    /// Display actions are skipped on AC when `disable_display_actions_on_ac` is set
+    fn is_display_suppressed(&self, action: &IdleAction) -> bool {
+        self.on_ac
+            && self.cfg.disable_display_actions_on_ac
+            && matches!(action.kind, IdleActionKind::Dpms | IdleActionKind::Brightness)
+    }
+
+    /// How long the idle task may sleep before something needs checking: the
+    /// next action, repeat or cooldown expiry, the warning, the end of
+    /// debounce, the inhibit warning or the next keep-alive key. Queued
+    /// action tasks start on their own. Kept between `MIN_TICK` and `MAX_TICK`.
+    pub fn next_deadline(&self) -> Duration {
+        let now = Instant::now();
+        let mut deadlines = Vec::new();
+
+        if let Some((_, remaining)) = self.next_action() {
+            deadlines.push(remaining);
+        }
+        if !self.warning_fired
+            && self.inhibit_source().is_none()
+            && let Some(warn_at) = self.warning_threshold(self.current_multiplier())
+        {
+            deadlines.push(warn_at.saturating_sub(self.elapsed_idle()));
+        }
+        if let Some(until) = self.debounce_until {
+            deadlines.push(until.saturating_duration_since(now));
+        }
+        if let Some(since) = self.inhibited_since
+            && self.cfg.inhibit_warn_after > 0
+            && !self.inhibit_warned
+        {
+            let warn_at = since + Duration::from_secs(self.cfg.inhibit_warn_after);
+            deadlines.push(warn_at.saturating_duration_since(now));
+        }
+        #[cfg(feature = "wlroots_virtual_keyboard")]
+        if self.cfg.keep_alive_seconds > 0 && self.inhibit_source().is_some() {
+            let next = self.last_keep_alive.map(|t| t + Duration::from_secs(self.cfg.keep_alive_seconds));
+            deadlines.push(next.map_or(Duration::ZERO, |t| t.saturating_duration_since(now)));
+        }
+
+        deadlines.into_iter().min().unwrap_or(MAX_TICK).clamp(MIN_TICK, MAX_TICK)
+    }
+
+    /// Wake the idle task to recompute its deadline, after changes that can
+    /// bring the next one closer (resume, reload, power switch)
+    fn rearm(&self) {
+        self.rearm.notify_one();
+    }
+
+    /// Schedule and power timeout multipliers combined, without logging schedule changes
+    fn current_multiplier(&self) -> f64 {
+        crate::schedule::active_entry(&self.cfg.schedule, chrono::Local::now())
+            .map(|e| e.multiplier)
+            .unwrap_or(1.0)
+            * self.power_multiplier()
+    }
+
+    /// `battery_timeout_scale` while on battery, 1.0 on AC. Derived battery
+    /// actions already carry `on_battery.timeout_scale`, so the two stack.
+    fn power_multiplier(&self) -> f64 {
+        if self.on_ac {
+            1.0
+        } else {
+            self.cfg.battery_timeout_scale.unwrap_or(1.0)
+        }
+    }
+
+    fn schedule_multiplier(&mut self) -> f64 {
+        let entry = crate::schedule::active_entry(&self.cfg.schedule, chrono::Local::now());
+        let name = entry.map(|e| e.name.clone());
+
+        if name != self.active_schedule {
+            match entry {
+                Some(e) => log_message(&format!("Schedule '{}' active, timeouts x{}", e.name, e.multiplier)),
+                None => log_message("No schedule active, using configured timeouts"),
+            }
+            self.active_schedule = name;
+        }
+
+        entry.map(|e| e.multiplier).unwrap_or(1.0)
+    }
+
+    pub fn reset(&mut self, source: ResetSource) {
+        self.set_reset_source(source);
+        if !self.had_activity {
+            self.had_activity = true;
+            if self.cfg.require_activity_first {
+                log_message("First activity seen, idle actions armed");
+            }
+        }
         self.last_activity = Instant::now();
         self.apply_reset();
 
-        let debounce_delay = Duration::from_secs(3);
-        self.debounce_until = Some(Instant::now() + debounce_delay);
+        self.debounce_until = Some(Instant::now() + ACTIVITY_DEBOUNCE);
+    }
+
+    fn set_reset_source(&mut self, source: ResetSource) {
+        if self.last_reset.is_none_or(|(last, _)| last != source) {
+            log_trace_message(&format!("Activity source: {}", source));
+        }
+        self.last_reset = Some((source, Instant::now()));
     }
 
     fn apply_reset(&mut self) {
-        let was_idle = self.is_idle_flags.iter().any(|&b| b);
+        let was_idle = self.is_idle();
+        if was_idle && let Some((source, _)) = self.last_reset {
+            log_message(&format!("Idle ended by {}", source));
+        }
         self.last_activity = Instant::now();
         self.cleanup_tasks();
-        self.is_idle_flags.fill(false);
+        self.abort_reversible_tasks();
+        self.restore_outputs();
+        self.clear_idle_flags();
+        self.warning_fired = false;
+        self.episode_active = false;
 
         if was_idle {
-            if let Some(state) = &self.previous_brightness {
-                restore_brightness(state);
-            }
-
-            if self.suspend_occurred {
-                if let Some(cmd) = &self.resume_command {
-                    let cmd_clone = cmd.clone();
-                    self.spawn_task_limited(async move {
-                        let _ = crate::actions::run_command_silent(&cmd_clone).await;
-                    });
-                }
-                self.suspend_occurred = false;
-            }
+            let _ = self.state_events.send(StateEvent::Active);
+            let resume_command = self.resume_command.clone().filter(|_| self.suspend_occurred);
+            self.run_resume_hooks(resume_command, Duration::ZERO);
+            self.suspend_occurred = false;
         }
 
         self.active_kinds.clear();
@@ -261,19 +938,88 @@ impl IdleTimer {
     }
 
     pub fn spawn_task_limited<F>(&mut self, fut: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_tagged(None, fut);
+    }
+
+    /// Like `spawn_task_limited`, but remembers the action so activity can
+    /// abort the task if the action is reversible
+    fn spawn_action_task<F>(&mut self, action: &IdleAction, fut: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.spawn_tagged(Some(action), fut);
+    }
+
+    fn spawn_tagged<F>(&mut self, action: Option<&IdleAction>, fut: F)
     where
         F: std::future::Future<Output = ()> + Send + 'static,
     {
         self.cleanup_tasks();
-        if self.spawned_tasks.len() < MAX_SPAWNED_TASKS {
-            self.spawned_tasks.push(tokio::spawn(fut));
-        } else {
-            log_message("Max spawned tasks reached, skipping task spawn");
+        let limit = self.cfg.max_concurrent_actions;
+        if self.spawned_tasks.len() >= limit {
+            // Slots go out in spawn order, so the tasks past the limit are the waiting ones
+            if self.spawned_tasks.len() - limit >= MAX_QUEUED_TASKS
+                && let Some(oldest) =
+                    (limit..self.spawned_tasks.len()).find(|&i| !self.spawned_tasks[i].started.load(Ordering::Relaxed))
+            {
+                self.spawned_tasks.remove(oldest).handle.abort();
+                log_error_message("Action queue full, dropped oldest queued task");
+            }
+            log_message(&format!(
+                "{} actions running, queued task ({} waiting)",
+                limit,
+                self.spawned_tasks.len() - limit + 1
+            ));
         }
+
+        let slots = Arc::clone(&self.action_slots);
+        let started = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&started);
+        let handle = tokio::spawn(async move {
+            // Held until the task ends, so the next waiting task starts right away
+            let Ok(_slot) = slots.acquire_owned().await else {
+                return;
+            };
+            flag.store(true, Ordering::Relaxed);
+            fut.await;
+        });
+        self.spawned_tasks.push(ActionTask {
+            kind: action.map(|a| a.kind.clone()),
+            reversible: action.is_some_and(is_reversible),
+            handle,
+            started,
+        });
     }
 
+    /// Forget finished tasks
     fn cleanup_tasks(&mut self) {
-        self.spawned_tasks.retain(|h| !h.is_finished());
+        self.spawned_tasks.retain(|t| !t.handle.is_finished());
+    }
+
+    /// Force the AC or battery profile, or go back to detection with None
+    pub async fn set_forced_power(&mut self, on_ac: Option<bool>) {
+        self.forced_power = on_ac;
+        let on_ac = match on_ac {
+            Some(on_ac) => {
+                log_message(&format!(
+                    "Power profile manually forced to {}, automatic detection suspended until set back to auto",
+                    if on_ac { "AC" } else { "Battery" }
+                ));
+                on_ac
+            }
+            None => {
+                let on_ac = crate::power_detection::is_on_ac_power(crate::utils::is_laptop());
+                log_message(&format!(
+                    "Power profile no longer forced, automatic detection resumed: {}",
+                    if on_ac { "AC" } else { "Battery" }
+                ));
+                on_ac
+            }
+        };
+        self.update_power_source(on_ac).await;
     }
 
     pub async fn update_power_source(&mut self, on_ac: bool) {
@@ -282,142 +1028,582 @@ impl IdleTimer {
         }
 
         self.on_ac = on_ac;
+        self.power_flips.push_back(Instant::now());
+        self.prune_power_flips();
+        if let Some(scale) = self.cfg.battery_timeout_scale {
+            if on_ac {
+                log_message("On AC, battery timeout scale no longer applied");
+            } else {
+                log_message(&format!("On battery, timeouts x{}", scale));
+            }
+        }
+        self.switch_action_set().await;
+    }
+
+    /// Record a battery reading and switch to the `on_low_battery` actions
+    /// while on battery below `low_threshold`
+    pub async fn update_battery_level(&mut self, percent: Option<u8>) {
+        self.battery_percent = percent;
+
+        let low = !self.on_ac
+            && !self.low_battery_actions.is_empty()
+            && self.cfg.low_battery_threshold.zip(percent).is_some_and(|(threshold, p)| p <= threshold);
+        if low == self.low_battery {
+            return;
+        }
+
+        self.low_battery = low;
+        if low {
+            log_message(&format!("Battery low ({}%), low battery actions active", percent.unwrap_or(0)));
+            if self.cfg.notify {
+                crate::notify::spawn_notification(
+                    "Battery low".to_string(),
+                    format!("{}% remaining, low battery actions are active", percent.unwrap_or(0)),
+                    crate::notify::Urgency::Critical,
+                );
+            }
+        } else {
+            log_message("Battery no longer low, battery actions restored");
+        }
+        self.switch_action_set().await;
+    }
+
+    /// Actions for the current power state. While the battery is low, each
+    /// low battery action replaces the battery action with the same key.
+    fn power_actions(&self) -> Vec<IdleAction> {
+        if self.on_ac {
+            return self.ac_actions.clone();
+        }
+        if !self.low_battery {
+            return self.battery_actions.clone();
+        }
+
+        let key = |a: &IdleAction| a.name.split_once('.').map(|(_, k)| k.to_string()).unwrap_or_default();
+        self.battery_actions
+            .iter()
+            .filter(|a| !self.low_battery_actions.iter().any(|low| key(low) == key(a)))
+            .chain(self.low_battery_actions.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Drop whatever the previous action set did and start over with the current one
+    async fn switch_action_set(&mut self) {
+        self.rearm();
         self.cleanup_tasks();
+        self.abort_reversible_tasks();
+        self.restore_outputs();
 
         if let Some(state) = self.previous_brightness.take() {
             restore_brightness(&state);
         }
 
-        self.actions = if on_ac { self.ac_actions.clone() } else { self.battery_actions.clone() };
+        if self.on_ac {
+            self.low_battery = false;
+        }
+        self.actions = self.power_actions();
         self.is_idle_flags = vec![false; self.actions.len()];
+        self.warning_fired = false;
+        self.episode_active = false;
         self.active_kinds.clear();
         self.trigger_instant_actions().await;
     }
 
+    fn prune_power_flips(&mut self) {
+        let hour = Duration::from_secs(3600);
+        while self.power_flips.front().is_some_and(|t| t.elapsed() > hour) {
+            self.power_flips.pop_front();
+        }
+    }
+
+    /// Number of AC/battery changes in the past hour, to spot flaky chargers
+    pub fn power_flips_last_hour(&self) -> usize {
+        let hour = Duration::from_secs(3600);
+        self.power_flips.iter().filter(|t| t.elapsed() <= hour).count()
+    }
+
     pub async fn trigger_idle(&mut self) {
         for i in 0..self.actions.len() {
-            if !self.is_idle_flags[i] {
+            if self.actions[i].enabled && !self.is_idle_flags[i] {
                 self.is_idle_flags[i] = true;
-                let action = self.actions[i].clone();                
+                let action = self.actions[i].clone();
+                let episode = self.begin_episode();
+                log_message(&format!("Idle episode #{}: action forced: {} kind={}", episode, action.name, action.kind));
+                let _ = self.action_events.send(action.name.clone());
                 let requests = crate::actions::prepare_action(&action).await;
-                for req in requests {
-                    match req {
-                        crate::actions::ActionRequest::PreSuspend => {
-                            self.trigger_pre_suspend(false, false).await;
+                self.dispatch_requests(&action, requests).await;
+
+            }
+        }
+    }
+
+    /// Start the lock_screen action's locker if it isn't up yet, returning
+    /// its command when the suspend has to wait for its process to appear
+    async fn start_locker(&mut self) -> Option<String> {
+        let Some(lock) = self
+            .actions
+            .iter()
+            .find(|a| a.kind == IdleActionKind::LockScreen && a.enabled && !a.command.trim().is_empty())
+            .cloned()
+        else {
+            log_error_message("lock_before_suspend is set but no lock_screen action is configured");
+            return None;
+        };
+        if crate::actions::is_process_running(&lock.command).await {
+            return None;
+        }
+
+        log_message(&format!("Locking before suspend: {}", lock.command));
+        let command = crate::actions::action_command(&lock);
+        let limits = RunLimits { kill_on_drop: false, timeout: None, kill_on_timeout: false };
+        let (name, kind_label) = (lock.name.clone(), lock.kind.to_string());
+        self.spawn_action_task(&lock, async move {
+            if let Err(e) = crate::actions::run_action_command(&command, limits).await {
+                log_error_message(&format!("Action {} (kind={}) failed: {}", name, kind_label, e));
+            }
+        });
+        (!crate::actions::is_dry_run()).then_some(lock.command)
+    }
+
+    /// Finish a suspend parked by `lock_before_suspend` once the locker is up
+    async fn finish_lock_wait(&mut self, wait: LockWait) {
+        self.trigger_pre_suspend(false, false).await;
+        self.run_requests(&wait.action, wait.rest).await;
+    }
+
+    /// Carry out the requests produced by `prepare_action`
+    async fn dispatch_requests(&mut self, action: &IdleAction, requests: Vec<ActionRequest>) {
+        *self.fire_counts.entry(action.name.clone()).or_default() += 1;
+        self.run_requests(action, requests).await;
+    }
+
+    async fn run_requests(&mut self, action: &IdleAction, requests: Vec<ActionRequest>) {
+        let kind = &action.kind;
+        let mut requests = requests.into_iter();
+        while let Some(req) = requests.next() {
+            match req {
+                ActionRequest::PreSuspend => {
+                    // Park the rest for the idle task, which waits for the locker unlocked
+                    if self.cfg.lock_before_suspend
+                        && let Some(locker) = self.start_locker().await
+                    {
+                        self.pending_lock_wait = Some(LockWait { action: action.clone(), rest: requests.collect(), locker });
+                        self.rearm();
+                        return;
+                    }
+                    self.trigger_pre_suspend(false, false).await;
+                }
+                ActionRequest::RunCommand(cmd) => {
+                    let limits = RunLimits {
+                        kill_on_drop: is_reversible(action),
+                        timeout: action.command_timeout_seconds.map(Duration::from_secs),
+                        // A locker is meant to keep running, only stop waiting on it
+                        kill_on_timeout: !matches!(kind, IdleActionKind::LockScreen),
+                    };
+                    let (name, kind_label) = (action.name.clone(), kind.to_string());
+                    self.spawn_action_task(action, async move {
+                        if let Err(e) = crate::actions::run_action_command(&cmd, limits).await {
+                            log_error_message(&format!("Action {} (kind={}) failed: {}", name, kind_label, e));
+                        }
+                    });
+                }
+                ActionRequest::Dim { target_percent, duration } if crate::actions::is_dry_run() => {
+                    log_message(&format!("DRY-RUN: would dim to {}% over {}s", target_percent, duration.as_secs()));
+                }
+                ActionRequest::Dim { target_percent, duration } => {
+                    self.spawn_action_task(action, async move {
+                        dim_to(target_percent, duration, DEFAULT_FADE_STEPS).await;
+                    });
+                }
+                ActionRequest::Sequence(steps) => {
+                    // One task for the whole chain so activity can abort it
+                    self.sequence_tasks.retain(|h| !h.is_finished());
+                    let slots = Arc::clone(&self.action_slots);
+                    self.sequence_tasks.push(tokio::spawn(async move {
+                        for step in steps {
+                            if step.delay_seconds > 0 {
+                                tokio::time::sleep(Duration::from_secs(step.delay_seconds)).await;
+                            }
+                            // Each step takes a slot like any other action command
+                            let Ok(_slot) = slots.acquire().await else {
+                                return;
+                            };
+                            log_message(&format!("Sequence step '{}': {}", step.name, step.command));
+                            // Killed with the step if activity aborts the sequence
+                            let limits = RunLimits { kill_on_drop: true, ..RunLimits::default() };
+                            let command = ActionCommand::Shell(step.command);
+                            if let Err(e) = crate::actions::run_action_command(&command, limits).await {
+                                log_error_message(&format!("Sequence step '{}' failed: {}", step.name, e));
+                            }
+                        }
+                    }));
+                }
+                #[cfg(feature = "wlr_output_power")]
+                ActionRequest::NativeDpms => {
+                    use crate::wayland::output_power::PowerMode;
+                    match &self.output_power {
+                        Some(_) if crate::actions::is_dry_run() => {
+                            log_message("DRY-RUN: would power off outputs");
                         }
-                        crate::actions::ActionRequest::RunCommand(cmd) => {
-                            let cmd_clone = cmd.clone();
-                            self.spawn_task_limited(async move {
-                                if let Err(e) = crate::actions::run_command_silent(&cmd_clone).await {
-                                    log_error_message(&format!("Failed to run command '{}': {}", cmd_clone, e));
-                                }
-                            });
+                        Some(output_power) => {
+                            output_power.set_all_outputs(PowerMode::Off);
+                            self.outputs_powered_off = true;
                         }
-                        crate::actions::ActionRequest::Skip(_) => {}
+                        None => log_error_message(
+                            "Dpms has no command and the compositor lacks wlr-output-power-management",
+                        ),
                     }
                 }
+                ActionRequest::LogindSleep(method) if crate::actions::is_dry_run() => {
+                    log_message(&format!("DRY-RUN: would {} through logind", method));
+                }
+                ActionRequest::LogindSleep(method) => {
+                    let interactive = self.cfg.suspend_interactive;
+                    self.spawn_action_task(action, async move {
+                        if let Err(e) = crate::suspend::logind_sleep(method, interactive).await {
+                            log_error_message(&format!("logind {} failed: {}", method, e));
+                        }
+                    });
+                }
+                ActionRequest::Skip(_) => {}
+            }
+        }
+    }
 
+    /// Abort running sequences and in-flight dim commands so they don't take
+    /// effect after activity. Everything else keeps running.
+    fn abort_reversible_tasks(&mut self) {
+        for handle in self.sequence_tasks.drain(..) {
+            if !handle.is_finished() {
+                handle.abort();
+                log_message("Activity detected, aborted running sequence");
             }
         }
+
+        self.spawned_tasks.retain(|task| match &task.kind {
+            Some(kind) if task.reversible && !task.handle.is_finished() => {
+                task.handle.abort();
+                if task.started.load(Ordering::Relaxed) {
+                    log_message(&format!("Activity detected, aborted running {} action", kind));
+                }
+                false
+            }
+            _ => true,
+        });
+    }
+
+    /// Run a named trigger's commands in order. Returns false if no such trigger exists.
+    pub fn run_trigger(&mut self, name: &str) -> bool {
+        let Some(commands) = self.cfg.triggers.get(name).cloned() else {
+            return false;
+        };
+
+        log_message(&format!("Running trigger '{}' ({} commands)", name, commands.len()));
+        *self.fire_counts.entry(format!("trigger.{}", name)).or_default() += 1;
+
+        let name = name.to_string();
+        self.spawn_task_limited(async move {
+            for cmd in commands {
+                if let Err(e) = crate::actions::run_command_silent(&cmd).await {
+                    log_error_message(&format!("Trigger '{}' command '{}' failed: {}", name, cmd, e));
+                    break;
+                }
+            }
+        });
+
+        true
     }
 
     pub async fn trigger_pre_suspend(&mut self, rewind_timers: bool, manual: bool) {
+        let _ = self.state_events.send(StateEvent::PreSuspend);
         if !manual {
             self.suspend_occurred = true;
         }
 
         if let Some(cmd) = &self.pre_suspend_command {
-            if let Err(e) = run_pre_suspend_sync(cmd) {
+            if let Err(e) = run_command_sync(cmd, self.sync_command_timeout()).await {
                 log_message(&format!("Pre-suspend command failed: {}", e));
             }
 
             if rewind_timers {
                 self.last_activity = Instant::now();
                 self.is_idle_flags.iter_mut().for_each(|f| *f = false);
+                self.warning_fired = false;
+                self.episode_active = false;
                 self.active_kinds.clear();
                 self.trigger_instant_actions().await;
             }
         }
     }
 
-    pub fn pause(&mut self, manually: bool) {
-        if manually {
-            self.manually_paused = true;
-            self.paused = false; // Clear automatic pause when manually pausing
-            log_message("Idle timers manually paused");
-        } else {
-            // Don't auto-pause if manually paused
-            if !self.manually_paused {
-                self.paused = true;
-                log_message("Idle timers automatically paused");
-            } else {
-                // Silently ignore automatic pause when manually paused
-            }
+    /// Manually pause idle timers until `resume`. Automatic inhibit sources
+    /// keep their own flags and are untouched.
+    pub fn pause(&mut self) {
+        self.rearm();
+        if !self.manually_paused {
+            let _ = self.state_events.send(StateEvent::ManualInhibitChanged(true));
         }
+        self.manually_paused = true;
+        log_message("Idle timers manually paused");
     }
 
-    pub fn resume(&mut self, manually: bool) {
-        if manually {
-            if self.manually_paused {
-                self.manually_paused = false;
-                self.paused = false; // Also clear automatic pause
-                log_message("Idle timers manually resumed");
-                
-                // Reset idle state when manually resuming
-                let was_idle = self.is_idle_flags.iter().any(|&b| b);
-                self.last_activity = Instant::now();
-                self.cleanup_tasks();
-                self.is_idle_flags.fill(false);
+    pub fn resume(&mut self) {
+        self.rearm();
+        self.cancel_inhibit_expiry();
+        if !self.manually_paused {
+            return;
+        }
+        self.manually_paused = false;
+        log_message("Idle timers manually resumed");
+        let _ = self.state_events.send(StateEvent::ManualInhibitChanged(false));
+        self.set_reset_source(ResetSource::ManualResume);
 
-                if was_idle {
-                    if let Some(state) = &self.previous_brightness {
-                        restore_brightness(state);
-                    }
+        // Reset idle state when manually resuming
+        let was_idle = self.is_idle();
+        self.last_activity = Instant::now();
+        self.cleanup_tasks();
+        self.abort_reversible_tasks();
+        self.restore_outputs();
+        self.clear_idle_flags();
+        self.warning_fired = false;
+        self.episode_active = false;
 
-                    if let Some(cmd) = &self.resume_command {
-                        let cmd_clone = cmd.clone();
-                        self.spawn_task_limited(async move {
-                            tokio::time::sleep(Duration::from_millis(200)).await;
-                            let _ = crate::actions::run_command_silent(&cmd_clone).await;
-                        });
-                    }
-                }
+        if was_idle {
+            self.send_wake_key();
+            let _ = self.state_events.send(StateEvent::Active);
+            self.run_resume_hooks(self.resume_command.clone(), Duration::from_millis(200));
+        }
 
-                self.active_kinds.clear();
-                self.previous_brightness = None;
+        self.active_kinds.clear();
+        self.previous_brightness = None;
+    }
+
+    /// Whether any automatic source (apps, media, ScreenSaver, lockfile) inhibits idle
+    pub fn auto_inhibited(&self) -> bool {
+        self.app_inhibited || self.media_playing || self.screensaver_inhibited || self.lockfile_inhibited
+    }
+
+    /// Record whether `source` inhibits idle. Timers pause when the first
+    /// source starts inhibiting and resume only once the last one clears.
+    pub fn set_inhibited(&mut self, source: InhibitSource, inhibited: bool) {
+        let was_inhibited = self.auto_inhibited();
+        let flag = match source {
+            InhibitSource::Apps => &mut self.app_inhibited,
+            InhibitSource::Media => &mut self.media_playing,
+            InhibitSource::ScreenSaver => &mut self.screensaver_inhibited,
+            InhibitSource::Lockfile => &mut self.lockfile_inhibited,
+        };
+        if *flag == inhibited {
+            return;
+        }
+        *flag = inhibited;
+        self.rearm();
+
+        if self.manually_paused {
+            return;
+        }
+        match (was_inhibited, self.auto_inhibited()) {
+            (false, true) => log_message("Idle timers automatically paused"),
+            (true, false) => self.auto_resume(),
+            _ => {}
+        }
+    }
+
+    /// The last automatic inhibit cleared, start idle over from now
+    fn auto_resume(&mut self) {
+        self.rearm();
+        log_message("Idle timers automatically resumed");
+        self.set_reset_source(ResetSource::AutoResume);
+
+        // Reset idle state when automatically resuming
+        let was_idle = self.is_idle();
+        self.last_activity = Instant::now();
+        self.cleanup_tasks();
+        self.abort_reversible_tasks();
+        self.restore_outputs();
+        self.clear_idle_flags();
+        self.warning_fired = false;
+        self.episode_active = false;
+
+        if was_idle {
+            self.send_wake_key();
+            let _ = self.state_events.send(StateEvent::Active);
+            self.run_resume_hooks(self.resume_command.clone(), Duration::from_millis(200));
+        }
+
+        self.active_kinds.clear();
+        self.previous_brightness = None;
+    }
+
+    /// Restore captured brightness right away, skipping any fade
+    pub fn force_restore_brightness(&mut self) {
+        match self.previous_brightness.take() {
+            Some(mut state) => {
+                state.fade = None;
+                restore_brightness(&state);
             }
+            None => log_message("No captured brightness to restore"),
+        }
+    }
+
+    /// Active actions paired with whether they have fired this idle period
+    pub fn action_states(&self) -> Vec<(String, bool)> {
+        self.actions
+            .iter()
+            .zip(self.is_idle_flags.iter())
+            .map(|(action, &is_idle)| (action.name.clone(), is_idle))
+            .collect()
+    }
+
+    /// Every configured action, sorted by key
+    pub fn configured_actions(&self) -> Vec<&IdleAction> {
+        let mut actions: Vec<_> = self.cfg.actions.values().collect();
+        actions.sort_by(|a, b| a.name.cmp(&b.name));
+        actions
+    }
+
+    pub fn pause_auto(&mut self) {
+        if !self.auto_paused {
+            self.auto_paused = true;
+            self.rearm();
+            log_message("Automatic idle actions paused, manual triggers still active");
+        }
+    }
+
+    pub fn set_presentation(&mut self, on: bool) {
+        if self.presentation == on {
+            return;
+        }
+        self.presentation = on;
+        self.rearm();
+        if on {
+            log_message("Presentation mode on, idle actions suspended");
         } else {
-            // Don't auto-resume if manually paused
-            if !self.manually_paused && self.paused {
-                self.paused = false;
-                log_message("Idle timers automatically resumed");
-                
-                // Reset idle state when automatically resuming
-                let was_idle = self.is_idle_flags.iter().any(|&b| b);
-                self.last_activity = Instant::now();
-                self.cleanup_tasks();
-                self.is_idle_flags.fill(false);
+            // Start counting from now so every overdue action doesn't fire at once
+            self.last_activity = Instant::now();
+            log_message("Presentation mode off");
+        }
+    }
 
-                if was_idle {
-                    if let Some(state) = &self.previous_brightness {
-                        restore_brightness(state);
-                    }
+    pub fn resume_auto(&mut self) {
+        if self.auto_paused {
+            self.auto_paused = false;
+            self.rearm();
+            // Start counting from now so every overdue action doesn't fire at once
+            self.last_activity = Instant::now();
+            log_message("Automatic idle actions resumed");
+        }
+    }
 
-                    if let Some(cmd) = &self.resume_command {
-                        let cmd_clone = cmd.clone();
-                        self.spawn_task_limited(async move {
-                            tokio::time::sleep(Duration::from_millis(200)).await;
-                            let _ = crate::actions::run_command_silent(&cmd_clone).await;
-                        });
-                    }
-                }
+    #[cfg(feature = "wlr_output_power")]
+    pub fn set_output_power(&mut self, output_power: crate::wayland::output_power::OutputPower) {
+        self.output_power = Some(output_power);
+    }
 
-                self.active_kinds.clear();
-                self.previous_brightness = None;
-            } else {
-                // Silently ignore automatic resume when manually paused
+    #[cfg(feature = "wlroots_virtual_keyboard")]
+    pub fn set_virtual_keyboard(&mut self, keyboard: crate::wayland::virtual_keyboard::VirtualKeyboard) {
+        self.virtual_keyboard = Some(keyboard);
+    }
+
+    /// While idle is inhibited, press a harmless key every `keep_alive_seconds`
+    /// so other idle systems (a DE's own idle, remote desktops) stay awake too
+    #[cfg(feature = "wlroots_virtual_keyboard")]
+    pub fn check_keep_alive(&mut self) {
+        let interval = self.cfg.keep_alive_seconds;
+        if interval == 0 || self.inhibit_source().is_none() {
+            self.last_keep_alive = None;
+            return;
+        }
+        let Some(keyboard) = &self.virtual_keyboard else {
+            return;
+        };
+
+        if self.last_keep_alive.is_none_or(|t| t.elapsed() >= Duration::from_secs(interval)) {
+            keyboard.send_keep_alive();
+            self.last_keep_alive = Some(Instant::now());
+        }
+    }
+
+    /// Timeout for hooks run to completion, `command_timeout_seconds` or 5s
+    fn sync_command_timeout(&self) -> Duration {
+        Duration::from_secs(self.cfg.command_timeout_seconds.unwrap_or(5))
+    }
+
+    /// Press `wake_key` so displays come back when idle ends without real input
+    fn send_wake_key(&self) {
+        #[cfg(feature = "wlroots_virtual_keyboard")]
+        if let Some(keyboard) = &self.virtual_keyboard {
+            keyboard.send_wake_key();
+        }
+    }
+
+    /// Run `pre_resume_command` to completion, then restore brightness and
+    /// start `resume_command` after `delay`. The hooks run in a task so a slow
+    /// pre-resume command doesn't hold up input, IPC or Wayland events.
+    fn run_resume_hooks(&mut self, resume_command: Option<String>, delay: Duration) {
+        let pre_resume = self.cfg.pre_resume_command.clone();
+        let timeout = self.sync_command_timeout();
+        let brightness = self.previous_brightness.clone();
+        self.spawn_task_limited(async move {
+            if let Some(cmd) = pre_resume
+                && let Err(e) = run_command_sync(&cmd, timeout).await
+            {
+                log_error_message(&format!("Pre-resume command failed: {}", e));
+            }
+
+            if let Some(state) = brightness {
+                restore_brightness(&state);
             }
+
+            if let Some(cmd) = resume_command {
+                tokio::time::sleep(delay).await;
+                if let Err(e) = crate::actions::run_command_silent(&cmd).await {
+                    log_error_message(&format!("Resume command failed: {}", e));
+                }
+            }
+        });
+    }
+
+    /// Turn outputs back on if a native dpms action powered them off
+    fn restore_outputs(&mut self) {
+        #[cfg(feature = "wlr_output_power")]
+        if self.outputs_powered_off && let Some(output_power) = &self.output_power {
+            output_power.set_all_outputs(crate::wayland::output_power::PowerMode::On);
+            self.outputs_powered_off = false;
+        }
+    }
+
+    pub fn set_config_watcher(&mut self, handle: JoinHandle<()>) {
+        self.config_watcher = Some(handle);
+    }
+
+    pub fn has_config_watcher(&self) -> bool {
+        self.config_watcher.as_ref().is_some_and(|h| !h.is_finished())
+    }
+
+    /// Stop watching the config file, after a reload turned auto_reload off
+    pub fn stop_config_watcher(&mut self) {
+        if let Some(handle) = self.config_watcher.take() {
+            handle.abort();
+            log_message("Auto-reload disabled, config watcher stopped");
+        }
+    }
+
+    pub fn set_media_monitor(&mut self, handle: JoinHandle<()>) {
+        self.media_monitor = Some(handle);
+    }
+
+    pub fn has_media_monitor(&self) -> bool {
+        self.media_monitor.is_some()
+    }
+
+    /// Stop the MPRIS monitor and lift any pause it was holding
+    pub fn stop_media_monitor(&mut self) {
+        if let Some(handle) = self.media_monitor.take() {
+            handle.abort();
+            log_message("Media monitor stopped");
         }
+        self.set_inhibited(InhibitSource::Media, false);
     }
 
     pub fn set_compositor_managed(&mut self, value: bool) {
@@ -428,26 +1614,59 @@ impl IdleTimer {
         self.compositor_managed
     }
 
+    /// Shortest timeout of the current action set, scaled like `check_idle` scales it
     pub fn shortest_timeout(&self) -> Duration {
+        let multiplier = self.current_multiplier();
         self.actions
             .iter()
-            .filter(|a| a.timeout_seconds > 0)
-            .map(|a| Duration::from_secs(a.timeout_seconds))
+            .filter(|a| a.enabled && a.timeout_seconds > 0)
+            .map(|a| Duration::from_secs(a.timeout_seconds).mul_f64(multiplier))
             .min()
             .unwrap_or_else(|| Duration::from_secs(60))
     }
 
+    /// Timeout for the compositor's idle notifications
+    pub fn compositor_timeout_ms(&self) -> u32 {
+        let base = self.shortest_timeout().as_millis() as i64;
+        // Margin lets users line the compositor's timer up with ours
+        (base + self.cfg.notification_margin_ms).clamp(1, u32::MAX as i64) as u32
+    }
+
+    /// Tell the Wayland loop if power, schedule, idle-delay or a reload changed the timeout
+    fn publish_compositor_timeout(&self) {
+        let ms = self.compositor_timeout_ms();
+        self.compositor_timeout.send_if_modified(|current| std::mem::replace(current, ms) != ms);
+    }
+
     pub fn mark_all_idle(&mut self) {
         self.is_idle_flags.fill(true);
     }
 
     pub async fn update_from_config(&mut self, cfg: &IdleConfig) {
+        self.rearm();
+        crate::actions::set_command_log_lines(cfg.command_log_lines);
+        crate::actions::set_command_timeout(cfg.command_timeout_seconds.unwrap_or(30));
+        crate::actions::set_suspend_method(cfg.suspend_method);
+        crate::log::set_log_file(cfg.log_file.as_ref().map(std::path::PathBuf::from));
+        crate::log::set_error_repeat_window(cfg.log_repeat_window_seconds);
+        self.fire_counts.clear();
         self.cleanup_tasks();
+        self.abort_reversible_tasks();
+        self.restore_outputs();
+
+        // The stored state may be stale, pick the profile from a fresh reading
+        let on_ac = self
+            .forced_power
+            .unwrap_or_else(|| crate::power_detection::is_on_ac_power(crate::utils::is_laptop()));
+        if on_ac != self.on_ac {
+            log_message(&format!("Power source re-detected on reload: {}", if on_ac { "AC" } else { "Battery" }));
+            self.on_ac = on_ac;
+        }
 
         let default_actions: Vec<_> = cfg
             .actions
             .iter()
-            .filter(|(k, _)| !k.starts_with("ac.") && !k.starts_with("battery."))
+            .filter(|(k, _)| !k.starts_with("ac.") && !k.starts_with("battery.") && !k.starts_with("low_battery."))
             .map(|(_, v)| v.clone())
             .collect();
 
@@ -465,25 +1684,39 @@ impl IdleTimer {
             .map(|(_, v)| v.clone())
             .collect();
 
+        self.low_battery_actions = cfg
+            .actions
+            .iter()
+            .filter(|(k, _)| k.starts_with("low_battery."))
+            .map(|(_, v)| v.clone())
+            .collect();
+        if self.low_battery_actions.is_empty() {
+            self.low_battery = false;
+        }
+
         self.actions = if !self.ac_actions.is_empty() || !self.battery_actions.is_empty() {
-            if self.on_ac {
-                self.ac_actions.clone()
-            } else {
-                self.battery_actions.clone()
-            }
+            self.power_actions()
         } else {
             default_actions
         };
 
+        if cfg.max_concurrent_actions != self.cfg.max_concurrent_actions {
+            // Running tasks keep the permits of the old limit until they end
+            self.action_slots = Arc::new(Semaphore::new(cfg.max_concurrent_actions));
+        }
         self.cfg = cfg.clone();
         self.is_idle_flags = vec![false; self.actions.len()];
+        self.warning_fired = false;
+        self.episode_active = false;
         self.resume_command = cfg.resume_command.clone();
         self.pre_suspend_command = cfg.pre_suspend_command.clone();
         self.last_activity = Instant::now();
         self.active_kinds.clear();
         self.previous_brightness = None;
+        self.apply_de_idle_delay();
 
         self.trigger_instant_actions().await;
+        self.reloads.send_replace(());
         log_message("Idle timers reloaded from config");
     }
 
@@ -492,49 +1725,438 @@ impl IdleTimer {
             handle.abort();
         }
 
-        for handle in self.spawned_tasks.drain(..) {
+        if let Some(handle) = self.config_watcher.take() {
+            handle.abort();
+        }
+
+        if let Some(handle) = self.media_monitor.take() {
+            handle.abort();
+        }
+
+        for task in self.spawned_tasks.drain(..) {
+            task.handle.abort();
+        }
+
+        for handle in self.sequence_tasks.drain(..) {
             handle.abort();
         }
     }
 }
 
-fn run_pre_suspend_sync(cmd: &str) -> Result<(), Box<dyn std::error::Error>> {
-    use std::process::Command;
-    use std::time::{Duration, Instant};
+/// Routine action logs, demoted to debug for actions marked `quiet`
+fn log_action(action: &IdleAction, message: &str) {
+    if action.quiet {
+        log_debug_message(message);
+    } else {
+        log_message(message);
+    }
+}
 
-    let mut child = Command::new("sh").arg("-c").arg(cmd).spawn()?;
-    let timeout = Duration::from_secs(5);
-    let start = Instant::now();
+/// Actions marked `reversible` are undone on activity, so an in-flight task
+/// can be aborted. Instant actions only run at startup and are left alone.
+fn is_reversible(action: &IdleAction) -> bool {
+    action.timeout_seconds > 0 && action.reversible
+}
 
-    loop {
-        if let Some(status) = child.try_wait()? {
-            if !status.success() {
-                return Err(format!("Command exited with status: {}", status).into());
-            }
-            return Ok(());
-        }
-        if start.elapsed() > timeout {
-            child.kill()?;
-            return Err("Pre-suspend command timed out".into());
+/// Run a command to completion, for hooks that must finish before we continue
+async fn run_command_sync(cmd: &str, timeout: Duration) -> Result<(), CommandError> {
+    use tokio::process::Command;
+
+    if crate::actions::dry_run_skip(cmd) {
+        return Ok(());
+    }
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(CommandError::Spawn)?;
+
+    match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => match status.map_err(CommandError::Spawn)? {
+            status if status.success() => Ok(()),
+            status => Err(CommandError::Exit(status)),
+        },
+        Err(_) => {
+            let _ = child.kill().await;
+            log_message(&format!("Command '{}' killed after exceeding its {}s timeout", cmd, timeout.as_secs()));
+            Err(CommandError::Timeout(timeout.as_secs()))
         }
-        std::thread::sleep(Duration::from_millis(100));
     }
 }
 
 /// Spawn main idle monitor task
 pub async fn spawn_idle_task(idle_timer: Arc<Mutex<IdleTimer>>) -> JoinHandle<()> {
     tokio::spawn(async move {
-        let mut ticker = tokio::time::interval(Duration::from_secs(1));
+        let rearm = Arc::clone(&idle_timer.lock().await.rearm);
 
         loop {
-            ticker.tick().await;
-            let mut timer = idle_timer.lock().await;
+            // Sleep until the next deadline instead of waking every second
+            let (deadline, pre_action, lock_wait) = {
+                let mut timer = idle_timer.lock().await;
+                timer.check_inhibit_duration();
+                #[cfg(feature = "wlroots_virtual_keyboard")]
+                timer.check_keep_alive();
+
+                // Only check idle if not manually paused
+                if !timer.manually_paused {
+                    timer.check_idle().await;
+                }
+                timer.publish_compositor_timeout();
+                (timer.next_deadline(), timer.pending_pre_action.take(), timer.pending_lock_wait.take())
+            };
+
+            // Unlocked, so input, IPC and Wayland events go through while these run
+            if let Some(wait) = lock_wait {
+                wait.run().await;
+                idle_timer.lock().await.finish_lock_wait(wait).await;
+                continue;
+            }
+            if let Some(check) = pre_action {
+                let allowed = check.run().await;
+                idle_timer.lock().await.finish_pre_action(check, allowed);
+                continue;
+            }
+            log_trace_message(&format!("Next idle check in {}ms", deadline.as_millis()));
 
-            // Only check idle if not manually paused
-            if !timer.manually_paused {
-                timer.check_idle().await;
+            tokio::select! {
+                _ = tokio::time::sleep(deadline) => {}
+                _ = rearm.notified() => {}
             }
         }
     })
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One custom action that is due after a second of idle
+    const CONFIG: &str = r#"
+idle:
+  force_desktop true
+  notify_me:
+    timeout 1
+    command "true"
+  end
+end
+"#;
+
+    fn timer(source: &str) -> IdleTimer {
+        IdleTimer::new(&crate::config::load_config_str(source).unwrap())
+    }
+
+    /// Pretend the last activity was `seconds` ago
+    fn idle_for(timer: &mut IdleTimer, seconds: u64) {
+        timer.debounce_until = None;
+        timer.last_activity = Instant::now() - Duration::from_secs(seconds);
+    }
+
+    fn fired(timer: &IdleTimer) -> u64 {
+        timer.fire_counts.values().sum()
+    }
+
+    #[tokio::test]
+    async fn one_source_clearing_keeps_another_inhibiting() {
+        let mut timer = timer(CONFIG);
+        timer.set_inhibited(InhibitSource::ScreenSaver, true);
+        timer.set_inhibited(InhibitSource::Media, true);
+        timer.set_inhibited(InhibitSource::Media, false);
+        assert!(timer.auto_inhibited());
+
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 0);
+
+        timer.set_inhibited(InhibitSource::ScreenSaver, false);
+        assert!(!timer.auto_inhibited());
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 1);
+    }
+
+    #[tokio::test]
+    async fn lockfile_keeps_inhibiting_when_media_stops() {
+        let mut timer = timer(CONFIG);
+        timer.set_inhibited(InhibitSource::Lockfile, true);
+        timer.set_inhibited(InhibitSource::Media, true);
+        timer.set_inhibited(InhibitSource::Media, false);
+
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 0);
+        assert_eq!(timer.inhibit_source(), Some("inhibit lockfile"));
+    }
+
+    #[tokio::test]
+    async fn queued_task_starts_when_a_slot_frees() {
+        let mut timer = timer(CONFIG);
+        timer.cfg.max_concurrent_actions = 1;
+        timer.action_slots = Arc::new(Semaphore::new(1));
+
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let (done, finished) = tokio::sync::oneshot::channel::<()>();
+        timer.spawn_task_limited(async move {
+            let _ = released.await;
+        });
+        timer.spawn_task_limited(async move {
+            let _ = done.send(());
+        });
+        tokio::task::yield_now().await;
+        assert!(!timer.spawned_tasks[1].started.load(Ordering::Relaxed));
+
+        // Nothing calls cleanup_tasks, the waiting task picks up the slot itself
+        release.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), finished).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn next_deadline_waits_for_repeat_and_cooldown() {
+        let mut timer = timer(CONFIG);
+        idle_for(&mut timer, 0);
+        let deadline = timer.next_deadline();
+        assert!(deadline <= Duration::from_secs(1), "{:?}", deadline);
+
+        // Due, but its kind fired 10s ago with a 30s cooldown
+        timer.actions[0].cooldown_seconds = 30;
+        let key = timer.actions[0].kind.to_string();
+        timer.last_fired.insert(key.clone(), Instant::now() - Duration::from_secs(10));
+        idle_for(&mut timer, 5);
+        let deadline = timer.next_deadline();
+        assert!(deadline > Duration::from_secs(18) && deadline <= Duration::from_secs(20), "{:?}", deadline);
+
+        // Fired and repeating, comes back at `repeat_at`
+        timer.last_fired.remove(&key);
+        timer.actions[0].repeat = true;
+        timer.is_idle_flags[0] = true;
+        timer.repeat_at.insert(timer.actions[0].name.clone(), Duration::from_secs(12));
+        let deadline = timer.next_deadline();
+        assert!(deadline > Duration::from_secs(5) && deadline <= Duration::from_secs(7), "{:?}", deadline);
+    }
+
+    #[tokio::test]
+    async fn suspend_waits_for_the_locker_outside_the_timer() {
+        let mut timer = timer(r#"
+idle:
+  force_desktop true
+  lock_before_suspend true
+  lock_screen:
+    timeout 100
+    command "stasis-test-no-such-locker"
+  end
+  suspend:
+    timeout 1
+    command "true"
+  end
+end
+"#);
+        idle_for(&mut timer, 5);
+        timer.check_idle().await;
+
+        // Parked for the idle task instead of polling under the lock
+        let wait = timer.pending_lock_wait.take().expect("suspend should wait for the locker");
+        assert_eq!(wait.locker, "stasis-test-no-such-locker");
+        assert!(matches!(wait.rest.as_slice(), [ActionRequest::RunCommand(_)]));
+        assert!(!timer.suspend_occurred);
+
+        timer.finish_lock_wait(wait).await;
+        assert!(timer.suspend_occurred);
+    }
+
+    #[tokio::test]
+    async fn instant_actions_do_not_begin_an_episode() {
+        let mut timer = timer(&CONFIG.replace("timeout 1", "timeout 0"));
+        let mut states = timer.state_events.subscribe();
+        timer.trigger_instant_actions().await;
+
+        assert_eq!(fired(&timer), 1);
+        assert_eq!(timer.idle_episode(), None);
+        assert!(states.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn compositor_timeout_follows_the_battery_scale() {
+        let mut timer = timer(&CONFIG.replace("timeout 1", "timeout 60").replace("force_desktop true", "force_desktop true\n  battery_timeout_scale 0.5"));
+        timer.on_ac = true;
+        let mut changes = timer.subscribe_compositor_timeout();
+        timer.publish_compositor_timeout();
+        assert_eq!(*changes.borrow_and_update(), 60_000);
+
+        // On battery the idle task republishes the scaled timeout
+        timer.on_ac = false;
+        timer.publish_compositor_timeout();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(*changes.borrow_and_update(), 30_000);
+
+        // Unchanged timeouts don't re-arm the notifications
+        timer.publish_compositor_timeout();
+        assert!(!changes.has_changed().unwrap());
+    }
+
+    const DIM_CONFIG: &str = r#"
+idle:
+  force_desktop true
+  dim_screen:
+    timeout 1
+    command "sleep 30"
+    reversible true
+  end
+  notify_me:
+    timeout 1
+    command "sleep 30"
+  end
+end
+"#;
+
+    #[tokio::test]
+    async fn activity_aborts_only_reversible_tasks() {
+        let mut timer = timer(DIM_CONFIG);
+        let dim = timer.actions.iter().find(|a| a.name.contains("dim")).unwrap().clone();
+        let notify = timer.actions.iter().find(|a| a.name.contains("notify")).unwrap().clone();
+        let mut instant = dim.clone();
+        instant.timeout_seconds = 0;
+
+        let slow = || tokio::time::sleep(Duration::from_secs(30));
+        let (dimming, dim_dropped) = tokio::sync::oneshot::channel::<()>();
+        timer.spawn_action_task(&dim, async move {
+            slow().await;
+            let _ = dimming.send(());
+        });
+        timer.spawn_action_task(&notify, slow());
+        timer.spawn_action_task(&instant, slow());
+        tokio::task::yield_now().await;
+
+        timer.abort_reversible_tasks();
+        // The aborted task drops its sender instead of finishing its sleep
+        assert!(tokio::time::timeout(Duration::from_secs(1), dim_dropped).await.unwrap().is_err());
+        assert_eq!(timer.spawned_tasks.len(), 2);
+        assert!(timer.spawned_tasks.iter().all(|t| !t.reversible && !t.handle.is_finished()));
+    }
+
+    #[tokio::test]
+    async fn pre_action_command_runs_outside_check_idle() {
+        let mut timer = timer(&CONFIG.replace("force_desktop true", "force_desktop true\n  pre_action_command \"true\""));
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 0);
+
+        // Activity while the command ran voids its approval
+        let check = timer.pending_pre_action.take().unwrap();
+        let allowed = check.run().await;
+        timer.last_activity = Instant::now() - Duration::from_secs(9);
+        timer.finish_pre_action(check, allowed);
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 0);
+
+        let check = timer.pending_pre_action.take().unwrap();
+        let allowed = check.run().await;
+        assert!(allowed);
+        timer.finish_pre_action(check, allowed);
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_pre_resume_command_does_not_block_reset() {
+        let mut timer = timer(&CONFIG.replace("force_desktop true", "force_desktop true\n  pre_resume_command \"sleep 5\""));
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        assert!(timer.is_idle());
+
+        let start = Instant::now();
+        timer.reset(ResetSource::Keyboard);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!timer.is_idle());
+    }
+
+    #[tokio::test]
+    async fn full_slots_queue_tasks_until_the_queue_is_full() {
+        let mut timer = timer(CONFIG);
+        timer.cfg.max_concurrent_actions = 1;
+        timer.action_slots = Arc::new(Semaphore::new(1));
+        let slow = || tokio::time::sleep(Duration::from_secs(30));
+
+        // Past the limit, tasks wait instead of being dropped
+        for _ in 0..=MAX_QUEUED_TASKS {
+            timer.spawn_task_limited(slow());
+        }
+        tokio::task::yield_now().await;
+        assert_eq!(timer.spawned_tasks.len(), MAX_QUEUED_TASKS + 1);
+        assert!(timer.spawned_tasks[0].started.load(Ordering::Relaxed));
+        assert!(timer.spawned_tasks[1..].iter().all(|t| !t.started.load(Ordering::Relaxed)));
+
+        // A full queue drops its oldest task for the new one
+        timer.spawn_task_limited(slow());
+        assert_eq!(timer.spawned_tasks.len(), MAX_QUEUED_TASKS + 1);
+        assert!(timer.spawned_tasks[0].started.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn repeated_resets_run_instant_actions_once() {
+        let mut timer = timer(&CONFIG.replace("timeout 1", "timeout 0"));
+        timer.init().await;
+        assert_eq!(fired(&timer), 1);
+
+        for _ in 0..3 {
+            timer.reset(ResetSource::Keyboard);
+            timer.trigger_instant_actions().await;
+        }
+        assert_eq!(fired(&timer), 1);
+    }
+
+    /// Idle long enough for the action, with its kind last fired `ago` seconds back
+    async fn check_with_cooldown(ago: u64) -> u64 {
+        let mut timer = timer(CONFIG);
+        timer.actions[0].cooldown_seconds = 30;
+        let key = timer.actions[0].kind.to_string();
+        timer.last_fired.insert(key, Instant::now() - Duration::from_secs(ago));
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        fired(&timer)
+    }
+
+    #[tokio::test]
+    async fn cooldown_holds_an_action_until_it_has_passed() {
+        assert_eq!(check_with_cooldown(29).await, 0);
+        assert_eq!(check_with_cooldown(30).await, 1);
+    }
+
+    #[tokio::test]
+    async fn only_repeat_actions_fire_again_while_idle() {
+        let mut timer = timer(CONFIG);
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 1);
+        assert!(timer.repeat_at.is_empty());
+
+        let mut timer = self::timer(CONFIG);
+        timer.actions[0].repeat = true;
+        idle_for(&mut timer, 10);
+        timer.check_idle().await;
+        let name = timer.actions[0].name.clone();
+        assert_eq!(timer.repeat_at.get(&name).map(Duration::as_secs), Some(11));
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 1);
+
+        idle_for(&mut timer, 12);
+        timer.check_idle().await;
+        assert_eq!(fired(&timer), 2);
+
+        // Activity ends the repeats
+        timer.reset(ResetSource::Keyboard);
+        assert!(timer.repeat_at.is_empty());
+    }
+
+    #[tokio::test]
+    async fn action_stats_count_each_firing() {
+        let mut timer = timer(CONFIG);
+        for _ in 0..2 {
+            idle_for(&mut timer, 10);
+            timer.check_idle().await;
+            timer.reset(ResetSource::Keyboard);
+        }
+        assert_eq!(timer.fire_counts.get(&timer.actions[0].name), Some(&2));
+    }
+}
@@ -1,13 +1,99 @@
+use std::collections::HashSet;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::os::unix::fs::OpenOptionsExt;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
 use std::os::unix::io::AsRawFd;
 use input::{Libinput, LibinputInterface};
-use input::event::Event;
+use input::event::{Event, EventTrait, KeyboardEvent, PointerEvent};
+use input::event::keyboard::{KeyState, KeyboardEventTrait};
+use input::event::pointer::ButtonState;
 use tokio::sync::Mutex;
 
-use crate::idle_timer::IdleTimer;
+use crate::config::{AppPattern, IdleConfig};
+use crate::idle_timer::{IdleTimer, ResetSource};
+use crate::log::{log_error_message, log_message};
+
+// Event types from linux/input-event-codes.h
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+
+/// Minimum gap between resets triggered by raw evdev events
+const EVDEV_RESET_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pointer travel is summed over this window before being dropped as jitter
+const JITTER_WINDOW: Duration = Duration::from_secs(1);
+
+/// Decides which libinput events count as activity. With a threshold set,
+/// only key and button presses and pointer motion adding up to at least the
+/// threshold within `JITTER_WINDOW` count, so a twitchy trackpad can't hold
+/// off idle forever. Events from `ignored_devices` never count.
+struct ActivityFilter {
+    threshold: Option<f64>,
+    dx: f64,
+    dy: f64,
+    since: Option<Instant>,
+    ignored_devices: Vec<AppPattern>,
+    /// Device names already logged as ignored
+    logged_devices: HashSet<String>,
+}
+
+impl ActivityFilter {
+    fn new(threshold: Option<f64>, ignored_devices: Vec<AppPattern>) -> Self {
+        Self { threshold, dx: 0.0, dy: 0.0, since: None, ignored_devices, logged_devices: HashSet::new() }
+    }
+
+    fn from_config(cfg: &IdleConfig) -> Self {
+        Self::new(cfg.ignore_input_jitter.then_some(cfg.input_jitter_threshold), cfg.ignore_input_devices.clone())
+    }
+
+    fn is_activity(&mut self, event: &Event) -> bool {
+        if !self.ignored_devices.is_empty() && self.is_ignored_device(event) {
+            return false;
+        }
+
+        let Some(threshold) = self.threshold else {
+            return matches!(event, Event::Keyboard(_) | Event::Pointer(_));
+        };
+
+        match event {
+            Event::Keyboard(KeyboardEvent::Key(key)) => key.key_state() == KeyState::Pressed,
+            Event::Pointer(PointerEvent::Button(button)) => button.button_state() == ButtonState::Pressed,
+            Event::Pointer(PointerEvent::MotionAbsolute(_)) => true,
+            Event::Pointer(PointerEvent::Motion(motion)) => self.add_motion(motion.dx(), motion.dy(), threshold),
+            _ => false,
+        }
+    }
+
+    fn is_ignored_device(&mut self, event: &Event) -> bool {
+        let name = event.device().name().to_string();
+        if !self.ignored_devices.iter().any(|p| p.is_match(&name)) {
+            return false;
+        }
+        if self.logged_devices.insert(name.clone()) {
+            log_message(&format!("Ignoring activity from input device '{}'", name));
+        }
+        true
+    }
+
+    fn add_motion(&mut self, dx: f64, dy: f64, threshold: f64) -> bool {
+        if self.since.is_none_or(|t| t.elapsed() > JITTER_WINDOW) {
+            self.dx = 0.0;
+            self.dy = 0.0;
+            self.since = Some(Instant::now());
+        }
+        self.dx += dx;
+        self.dy += dy;
+
+        if self.dx.hypot(self.dy) < threshold {
+            return false;
+        }
+        self.since = None;
+        true
+    }
+}
 
 /// Minimal libinput interface
 struct MyInterface;
@@ -41,36 +127,56 @@ pub fn spawn_input_task(idle_timer: Arc<Mutex<IdleTimer>>) {
         // Silence libinput errors
         silence_stderr();
 
+        let rt = tokio::runtime::Handle::current();
+
         let mut li = Libinput::new_with_udev(MyInterface);
         if let Err(e) = li.udev_assign_seat("seat0") {
-            eprintln!("Failed to assign seat: {:?}", e);
+            log_error_message(&format!("Failed to assign libinput seat: {:?}", e));
+
+            let devices = rt.block_on(async { idle_timer_clone.lock().await.cfg.input_devices.clone() });
+            if devices.is_empty() {
+                return;
+            }
+            log_message("Falling back to raw evdev input devices");
+            for path in devices {
+                spawn_evdev_task(Arc::clone(&idle_timer_clone), path);
+            }
             return;
         }
 
-        let rt = tokio::runtime::Handle::current();
+        let (mut filter, mut reloads) = rt.block_on(async {
+            let timer = idle_timer_clone.lock().await;
+            (ActivityFilter::from_config(&timer.cfg), timer.subscribe_reloads())
+        });
 
         loop {
+            // Pick up jitter and ignored device changes from a reload
+            if reloads.has_changed().unwrap_or(false) {
+                reloads.mark_unchanged();
+                filter = rt.block_on(async { ActivityFilter::from_config(&idle_timer_clone.lock().await.cfg) });
+            }
+
             // Dispatch events
             if li.dispatch().is_err() {
                 std::thread::sleep(Duration::from_millis(10));
                 continue;
             }
 
-            // Batch events
-            let mut reset_needed = false;
+            // Batch events, the last one decides the reported source
+            let mut reset_source = None;
             while let Some(event) = li.next() {
-                match event {
-                    Event::Keyboard(_) | Event::Pointer(_) => {
-                        reset_needed = true;
-                    }
-                    _ => {}
+                if filter.is_activity(&event) {
+                    reset_source = Some(match event {
+                        Event::Keyboard(_) => ResetSource::Keyboard,
+                        _ => ResetSource::Pointer,
+                    });
                 }
             }
 
-            if reset_needed {
+            if let Some(source) = reset_source {
                 rt.block_on(async {
                     let mut timer = idle_timer_clone.lock().await;
-                    timer.reset();
+                    timer.reset(source);
                 });
             }
 
@@ -79,6 +185,49 @@ pub fn spawn_input_task(idle_timer: Arc<Mutex<IdleTimer>>) {
     });
 }
 
+/// Read raw `input_event`s from a `/dev/input/eventN` device and reset the
+/// IdleTimer on key, relative or absolute motion events
+fn spawn_evdev_task(idle_timer: Arc<Mutex<IdleTimer>>, path: String) {
+    tokio::task::spawn_blocking(move || {
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                log_error_message(&format!("Failed to open input device {}: {}", path, e));
+                return;
+            }
+        };
+        log_message(&format!("Watching input device {}", path));
+
+        let rt = tokio::runtime::Handle::current();
+        // struct input_event ends with: u16 type, u16 code, i32 value
+        let size = std::mem::size_of::<libc::input_event>();
+        let mut buf = vec![0u8; size];
+        let mut last_reset: Option<Instant> = None;
+
+        loop {
+            if let Err(e) = file.read_exact(&mut buf) {
+                log_error_message(&format!("Stopped reading input device {}: {}", path, e));
+                return;
+            }
+
+            let ev_type = u16::from_ne_bytes([buf[size - 8], buf[size - 7]]);
+            if !matches!(ev_type, EV_KEY | EV_REL | EV_ABS) {
+                continue;
+            }
+
+            if last_reset.is_some_and(|t| t.elapsed() < EVDEV_RESET_INTERVAL) {
+                continue;
+            }
+            last_reset = Some(Instant::now());
+
+            rt.block_on(async {
+                let mut timer = idle_timer.lock().await;
+                timer.reset(ResetSource::InputDevice);
+            });
+        }
+    });
+}
+
 /// Redirect libinput stderr to /dev/null to avoid spam
 fn silence_stderr() {
     if let Ok(dev_null) = OpenOptions::new().write(true).open("/dev/null") {
@@ -88,3 +237,18 @@ fn silence_stderr() {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_motion_below_the_threshold_is_jitter() {
+        let mut filter = ActivityFilter::new(Some(10.0), Vec::new());
+        assert!(!filter.add_motion(3.0, 4.0, 10.0));
+        assert!(!filter.add_motion(-1.0, 0.0, 10.0));
+
+        // Travel adds up within the window until it reaches the threshold
+        assert!(filter.add_motion(4.0, 4.0, 10.0));
+        assert!(!filter.add_motion(1.0, 1.0, 10.0));
+    }
+}
@@ -1,12 +1,15 @@
-use std::sync::Arc;
-use tokio::net::UnixListener;
+use std::{path::{Path, PathBuf}, sync::Arc, time::Duration};
+use tokio::net::{UnixListener, UnixStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
 
 use crate::{
     app_inhibit::AppInhibitor,
     config,
-    idle_timer::IdleTimer,
-    log::{log_error_message, log_message},
+    error::IpcError,
+    idle_timer::{IdleTimer, StateEvent},
+    log::{log_debug_message, log_error_message, log_message},
+    wayland::WaylandIdleData,
     SOCKET_PATH,
 };
 
@@ -14,153 +17,719 @@ use crate::{
 pub async fn spawn_control_socket_with_listener(
     idle_timer: Arc<tokio::sync::Mutex<IdleTimer>>,
     app_inhibitor: Arc<tokio::sync::Mutex<AppInhibitor>>,
-    cfg_path: String,
+    wl_data: Arc<tokio::sync::Mutex<WaylandIdleData>>,
+    cfg_path: PathBuf,
+    reloader: ReloadHandle,
     listener: UnixListener,
 ) {
     tokio::spawn(async move {
         loop {
-            if let Ok((mut stream, _addr)) = listener.accept().await {
-                let mut buf = vec![0u8; 64];
-                if let Ok(n) = stream.read(&mut buf).await {
-                    let cmd = String::from_utf8_lossy(&buf[..n]).trim().to_string();
-
-                    match cmd.as_str() {
-                        "reload" => {
-                            match config::load_config(&cfg_path) {
-                                Ok(new_cfg) => {
-                                    let mut timer = idle_timer.lock().await;
-                                    timer.update_from_config(&new_cfg).await;
-                                    log_message("Config reloaded successfully");
-                                }
-                                Err(_) => log_error_message("Failed to reload config"),
-                            }
-                        }
+            let Ok((stream, _addr)) = listener.accept().await else {
+                continue;
+            };
 
-                        "pause" => {
-                            let mut timer = idle_timer.lock().await;
-                            timer.pause(true);
-                            log_message("Idle timers paused");
-                        }
+            if let Err(e) = handle_connection(stream, &idle_timer, &app_inhibitor, &wl_data, &cfg_path, &reloader).await {
+                log_error_message(&e.to_string());
+            }
+        }
+    });
+}
 
-                        "resume" => {
-                            let mut timer = idle_timer.lock().await;
-                            timer.resume(true);
-                            log_message("Idle timers resumed");
-                        }
+/// A parsed config and who to tell once it has been applied
+type ReloadRequest = (config::IdleConfig, Option<oneshot::Sender<()>>);
 
-                        "trigger_idle" => {
-                            let mut timer = idle_timer.lock().await;
-                            timer.trigger_idle().await;
-                            log_message("Forced idle actions triggered");
-                        }
+/// Handle to the task that applies reloads, see `spawn_reload_task`
+#[derive(Clone)]
+pub struct ReloadHandle(mpsc::UnboundedSender<ReloadRequest>);
 
-                        "trigger_presuspend" => {
-                            let mut timer = idle_timer.lock().await;
-                            timer.trigger_pre_suspend(false, true).await;
-                            log_message("Pre-suspend command triggered");
-                        }
+impl ReloadHandle {
+    /// Queue a parsed config to be applied once `reload_debounce_ms` passes
+    pub fn request(&self, cfg: config::IdleConfig) {
+        let _ = self.0.send((cfg, None));
+    }
 
-                        "stop" => {
-                            log_message("Received stop command, shutting down gracefully");
-
-                            let idle_timer_clone = Arc::clone(&idle_timer);
-                            tokio::spawn(async move {
-                                let mut timer = idle_timer_clone.lock().await;
-                                timer.shutdown().await;
-                                log_message("IdleTimer shutdown complete, exiting process");
-                                let _ = std::fs::remove_file(SOCKET_PATH);
-                                std::process::exit(0);
-                            });
-                        }
+    /// Queue a parsed config like `request` and wait until it (or a newer
+    /// one) has been applied. False if the reload task is gone.
+    pub async fn request_and_wait(&self, cfg: config::IdleConfig) -> bool {
+        let (done, applied) = oneshot::channel();
+        let _ = self.0.send((cfg, Some(done)));
+        applied.await.is_ok()
+    }
 
-                        "toggle_inhibit" => {
-                            let mut timer = idle_timer.lock().await;
-                            let currently_inhibited = timer.is_manually_inhibited();
+    /// A handle whose requests land in the returned receiver instead of a reload task
+    #[cfg(test)]
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<ReloadRequest>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self(tx), rx)
+    }
+}
 
-                            if currently_inhibited {
-                                timer.set_manual_inhibit(false).await;
-                                log_message("Manual inhibit disabled (toggle)");
-                            } else {
-                                timer.set_manual_inhibit(true).await;
-                                log_message("Manual inhibit enabled (toggle)");
-                            }
+/// Apply reloads in the background, off the control socket loop. Configs
+/// arriving within `reload_debounce_ms` of the first are coalesced and only
+/// the newest applies.
+pub fn spawn_reload_task(
+    idle_timer: Arc<tokio::sync::Mutex<IdleTimer>>,
+    wl_data: Arc<tokio::sync::Mutex<WaylandIdleData>>,
+    cfg_path: PathBuf,
+) -> ReloadHandle {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    // For a config watcher started by a reload that turns auto_reload on
+    let reloader = ReloadHandle(tx.clone());
 
-                            // Send JSON response for Waybar feedback
-                            let response = if currently_inhibited {
-                                serde_json::json!({
-                                    "text": "⌚",
-                                    "tooltip": "Idle inhibition cleared"
-                                })
-                            } else {
-                                serde_json::json!({
-                                    "text": "🚫",
-                                    "tooltip": "Idle inhibition active"
-                                })
-                            };
-
-                            if let Err(e) = stream.write_all(response.to_string().as_bytes()).await {
-                                log_error_message(&format!("Failed to send toggle response: {e}"));
-                            }
+    tokio::spawn(async move {
+        while let Some((mut new_cfg, waiter)) = rx.recv().await {
+            let window = Duration::from_millis(idle_timer.lock().await.cfg.reload_debounce_ms);
+            let deadline = tokio::time::sleep(window);
+            tokio::pin!(deadline);
+
+            // Everyone waiting on a superseded config is answered with the newest
+            let mut waiters: Vec<_> = waiter.into_iter().collect();
+            let mut superseded = 0;
+            loop {
+                tokio::select! {
+                    _ = &mut deadline => break,
+                    Some((newer, waiter)) = rx.recv() => {
+                        new_cfg = newer;
+                        waiters.extend(waiter);
+                        superseded += 1;
+                    }
+                }
+            }
+            if superseded > 0 {
+                log_debug_message(&format!("Coalesced {} superseded reload requests", superseded));
+            }
+
+            apply_config(new_cfg, &idle_timer, &wl_data, &cfg_path, &reloader).await;
+            for waiter in waiters {
+                let _ = waiter.send(());
+            }
+        }
+    });
+
+    ReloadHandle(tx)
+}
+
+/// Apply a freshly loaded config to the running daemon
+async fn apply_config(
+    new_cfg: config::IdleConfig,
+    idle_timer: &Arc<tokio::sync::Mutex<IdleTimer>>,
+    wl_data: &Arc<tokio::sync::Mutex<WaylandIdleData>>,
+    cfg_path: &Path,
+    reloader: &ReloadHandle,
+) {
+    let mut timer = idle_timer.lock().await;
+    timer.update_from_config(&new_cfg).await;
+    wl_data.lock().await.respect_inhibitors = new_cfg.respect_idle_inhibitors;
+
+    // Follow a toggled monitor_media
+    match (new_cfg.monitor_media, timer.has_media_monitor()) {
+        (true, false) => match crate::media::spawn_media_monitor(Arc::clone(idle_timer)) {
+            Ok(handle) => timer.set_media_monitor(handle),
+            Err(e) => log_error_message(&format!("Failed to start media monitor: {}", e)),
+        },
+        (false, true) => timer.stop_media_monitor(),
+        _ => {}
+    }
+
+    // Follow a toggled auto_reload
+    match (new_cfg.auto_reload, timer.has_config_watcher()) {
+        (true, false) => {
+            if let Some(handle) = crate::config_watch::spawn_config_watcher(cfg_path.to_path_buf(), reloader.clone()) {
+                timer.set_config_watcher(handle);
+            }
+        }
+        (false, true) => timer.stop_config_watcher(),
+        _ => {}
+    }
+    log_message("Config reloaded successfully");
+}
+
+/// Fields accepted by `query <field>`
+const QUERY_FIELDS: &[&str] = &[
+    "idle_seconds",
+    "uptime_seconds",
+    "on_ac",
+    "inhibited",
+    "paused",
+    "battery_percent",
+    "inhibit_remaining_seconds",
+    "presentation",
+];
+
+/// Read a single control command from the stream and act on it
+async fn handle_connection(
+    mut stream: UnixStream,
+    idle_timer: &Arc<tokio::sync::Mutex<IdleTimer>>,
+    app_inhibitor: &Arc<tokio::sync::Mutex<AppInhibitor>>,
+    wl_data: &Arc<tokio::sync::Mutex<WaylandIdleData>>,
+    cfg_path: &Path,
+    reloader: &ReloadHandle,
+) -> Result<(), IpcError> {
+    let mut buf = vec![0u8; 64];
+    let n = stream.read(&mut buf).await.map_err(IpcError::Read)?;
+    let cmd = String::from_utf8_lossy(&buf[..n]).trim().to_string();
+
+    match cmd.as_str() {
+        "reload" => {
+            // Parse here so `stasis reload` can show errors right away,
+            // the reload task applies it after the debounce window
+            match config::load_config(cfg_path) {
+                Ok(new_cfg) => {
+                    let mut response = format!("Config reloaded: {} actions loaded", new_cfg.actions.len());
+                    for warning in &new_cfg.warnings {
+                        response.push_str(&format!("\n{}", warning));
+                    }
+
+                    // Reply once it's applied, from its own task so the socket keeps serving
+                    let reloader = reloader.clone();
+                    tokio::spawn(async move {
+                        if !reloader.request_and_wait(new_cfg).await {
+                            response = "Reload failed: the reload task is not running".to_string();
                         }
+                        let _ = stream.write_all(response.as_bytes()).await;
+                    });
+                }
+                Err(e) => {
+                    let err = IpcError::Reload(e);
+                    log_error_message(&err.to_string());
+                    stream
+                        .write_all(err.to_string().as_bytes())
+                        .await
+                        .map_err(|source| IpcError::Write { what: "reload response", source })?;
+                }
+            }
+        }
+
+        "pause" => {
+            let mut timer = idle_timer.lock().await;
+            timer.pause();
+            log_message("Idle timers paused");
+        }
+
+        "resume" => {
+            let mut timer = idle_timer.lock().await;
+            timer.resume();
+            log_message("Idle timers resumed");
+        }
+
+        "pause_auto" => {
+            let mut timer = idle_timer.lock().await;
+            timer.pause_auto();
+        }
 
-                        "info" | "info --json" => {
-                            let as_json = cmd.contains("--json");
-
-                            let idle = idle_timer.lock().await;
-                            let idle_time = idle.elapsed_idle();
-                            let mut inhibitor = app_inhibitor.lock().await;
-                            let app_blocking = inhibitor.is_any_app_running().await;
-                            let idle_inhibited = idle.paused || idle.manually_paused || app_blocking;
-                            let uptime = idle.start_time.elapsed();
-
-                            if as_json {
-                                let output = if idle_inhibited {
-                                    serde_json::json!({
-                                        "text": "☕",
-                                        "tooltip": format!(
-                                            "Idle inhibited\nIdle time: {}s\nUptime: {}s\nPaused: {}\nManually paused: {}\nApp blocking: {}",
-                                            idle_time.as_secs(),
-                                            uptime.as_secs(),
-                                            idle.paused,
-                                            idle.manually_paused,
-                                            app_blocking
-                                        )
-                                    })
-                                } else {
-                                    serde_json::json!({
-                                        "text": "⌚",
-                                        "tooltip": format!(
-                                            "Idle active\nIdle time: {}s\nUptime: {}s\nPaused: {}\nManually paused: {}\nApp blocking: {}",
-                                            idle_time.as_secs(),
-                                            uptime.as_secs(),
-                                            idle.paused,
-                                            idle.manually_paused,
-                                            app_blocking
-                                        )
-                                    })
-                                };
-
-                                if let Err(e) = stream.write_all(output.to_string().as_bytes()).await {
-                                    log_error_message(&format!("Failed to send JSON info: {e}"));
-                                }
-                            } else {
-                                let stats = idle.cfg.pretty_print(
-                                    Some(idle_time),
-                                    Some(uptime),
-                                    Some(idle_inhibited),
-                                );
-
-                                if let Err(e) = stream.write_all(stats.as_bytes()).await {
-                                    log_error_message(&format!("Failed to send info: {e}"));
-                                }
+        "resume_auto" => {
+            let mut timer = idle_timer.lock().await;
+            timer.resume_auto();
+        }
+
+        "restore_brightness" => {
+            let mut timer = idle_timer.lock().await;
+            timer.force_restore_brightness();
+        }
+
+        "trigger_idle" => {
+            let mut timer = idle_timer.lock().await;
+            timer.trigger_idle().await;
+            log_message("Forced idle actions triggered");
+        }
+
+        "trigger_presuspend" => {
+            let mut timer = idle_timer.lock().await;
+            timer.trigger_pre_suspend(false, true).await;
+            log_message("Pre-suspend command triggered");
+        }
+
+        _ if cmd.starts_with("trigger ") => {
+            let name = cmd.trim_start_matches("trigger ").trim();
+            let mut timer = idle_timer.lock().await;
+
+            let response = if timer.run_trigger(name) {
+                format!("Triggered '{}'", name)
+            } else {
+                log_error_message(&format!("Unknown trigger: {}", name));
+                format!("Unknown trigger '{}'", name)
+            };
+
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "trigger response", source })?;
+        }
+
+        _ if cmd == "subscribe_idle" || cmd.starts_with("subscribe_idle ") => {
+            // Keeps the stream open and writes one line on the next idle transition.
+            // An optional action name waits for that action instead, with or without
+            // its power prefix, as long as it fires within an idle episode.
+            let wanted = cmd.trim_start_matches("subscribe_idle").trim().replace('_', "-");
+            let (mut states, mut actions) = {
+                let timer = idle_timer.lock().await;
+                (timer.subscribe_state(), timer.subscribe_actions())
+            };
+            let idle_timer = Arc::clone(idle_timer);
+
+            // Answer from its own task so the socket keeps serving other commands
+            tokio::spawn(async move {
+                use tokio::sync::broadcast::error::RecvError;
+
+                let line = if wanted.is_empty() {
+                    loop {
+                        match states.recv().await {
+                            Ok(StateEvent::Idle) => break "idle".to_string(),
+                            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return,
+                        }
+                    }
+                } else {
+                    loop {
+                        match actions.recv().await {
+                            // Instant actions at startup or on a power switch aren't idle
+                            Ok(name) if (name == wanted || name.split_once('.').is_some_and(|(_, key)| key == wanted))
+                                && idle_timer.lock().await.idle_episode().is_some() =>
+                            {
+                                break name;
                             }
+                            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return,
                         }
+                    }
+                };
+                let _ = stream.write_all(format!("{}\n", line).as_bytes()).await;
+            });
+        }
+
+        _ if cmd.starts_with("presentation ") => {
+            let value = cmd.trim_start_matches("presentation ").trim();
+
+            let response = match value {
+                "on" | "true" | "1" | "off" | "false" | "0" => {
+                    let on = matches!(value, "on" | "true" | "1");
+                    idle_timer.lock().await.set_presentation(on);
+                    format!("Presentation mode {}", if on { "on" } else { "off" })
+                }
+                other => format!("Invalid value '{}', expected on or off", other),
+            };
+
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "presentation response", source })?;
+        }
+
+        _ if cmd.starts_with("set_power ") => {
+            let value = cmd.trim_start_matches("set_power ").trim();
+            let forced = match value {
+                "ac" => Some(Some(true)),
+                "battery" => Some(Some(false)),
+                "auto" => Some(None),
+                _ => None,
+            };
+
+            let response = match forced {
+                Some(forced) => {
+                    let mut timer = idle_timer.lock().await;
+                    timer.set_forced_power(forced).await;
+                    format!("Power profile: {}", power_profile(&timer))
+                }
+                None => format!("Invalid value '{}', expected ac, battery or auto", value),
+            };
+
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "set_power response", source })?;
+        }
+
+        "get_power" => {
+            let response = power_profile(&*idle_timer.lock().await);
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "get_power response", source })?;
+        }
+
+        _ if cmd.starts_with("set_respect_inhibitors ") => {
+            let value = cmd.trim_start_matches("set_respect_inhibitors ").trim();
+
+            let response = match value {
+                "true" | "on" | "1" | "false" | "off" | "0" => {
+                    let respect = matches!(value, "true" | "on" | "1");
+                    // Keep the config copy in sync so `info` shows the effective value
+                    idle_timer.lock().await.cfg.respect_idle_inhibitors = respect;
+                    wl_data.lock().await.respect_inhibitors = respect;
+                    log_message(&format!("respect_idle_inhibitors set to {} via IPC", respect));
+                    format!("respect_idle_inhibitors = {}", respect)
+                }
+                other => format!("Invalid value '{}', expected true or false", other),
+            };
+
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "set_respect_inhibitors response", source })?;
+        }
+
+        "stop" => {
+            log_message("Received stop command, shutting down gracefully");
+            #[cfg(feature = "systemd")]
+            crate::systemd::notify_stopping();
+
+            let idle_timer_clone = Arc::clone(idle_timer);
+            tokio::spawn(async move {
+                let mut timer = idle_timer_clone.lock().await;
+                timer.shutdown().await;
+                log_message("IdleTimer shutdown complete, exiting process");
+                let _ = std::fs::remove_file(&*SOCKET_PATH);
+                std::process::exit(0);
+            });
+        }
+
+        _ if cmd == "inhibit" || cmd.starts_with("inhibit ") => {
+            let arg = cmd.trim_start_matches("inhibit").trim();
+            let duration = match arg {
+                "" => None,
+                secs => match secs.parse::<u64>() {
+                    Ok(secs) => Some(Duration::from_secs(secs)),
+                    Err(_) => return Err(IpcError::UnknownCommand(cmd)),
+                },
+            };
+
+            let mut timer = idle_timer.lock().await;
+            timer.cancel_inhibit_expiry();
+            timer.set_manual_inhibit(true).await;
+
+            let response = match duration {
+                Some(duration) => {
+                    let idle_timer = Arc::clone(idle_timer);
+                    let task = tokio::spawn(async move {
+                        tokio::time::sleep(duration).await;
+                        let mut timer = idle_timer.lock().await;
+                        log_message("Timed manual inhibit expired");
+                        timer.set_manual_inhibit(false).await;
+                    });
+                    timer.set_inhibit_expiry(duration, task);
+
+                    let duration = crate::utils::format_duration(duration);
+                    log_message(&format!("Manual inhibit enabled for {}", duration));
+                    format!("Idle inhibited for {}", duration)
+                }
+                None => {
+                    log_message("Manual inhibit enabled until resumed");
+                    "Idle inhibited until `stasis resume`".to_string()
+                }
+            };
+
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "inhibit response", source })?;
+        }
+
+        "toggle_inhibit" => {
+            let mut timer = idle_timer.lock().await;
+            let currently_inhibited = timer.is_manually_inhibited();
+
+            if currently_inhibited {
+                timer.set_manual_inhibit(false).await;
+                log_message("Manual inhibit disabled (toggle)");
+            } else {
+                timer.set_manual_inhibit(true).await;
+                log_message("Manual inhibit enabled (toggle)");
+            }
+
+            // Send JSON response for Waybar feedback
+            let response = if currently_inhibited {
+                serde_json::json!({
+                    "text": "⌚",
+                    "tooltip": "Idle inhibition cleared"
+                })
+            } else {
+                serde_json::json!({
+                    "text": "🚫",
+                    "tooltip": "Idle inhibition active"
+                })
+            };
 
-                        _ => log_error_message(&format!("Unknown control command: {}", cmd)),
+            stream
+                .write_all(response.to_string().as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "toggle response", source })?;
+        }
+
+        "list_actions" | "list_actions --json" => {
+            let timer = idle_timer.lock().await;
+            let actions = timer.configured_actions();
+            let mut triggers: Vec<_> = timer.cfg.triggers.keys().collect();
+            triggers.sort();
+
+            let response = if cmd.contains("--json") {
+                serde_json::json!({
+                    "actions": actions.iter().map(|a| serde_json::json!({
+                        "key": a.name,
+                        "kind": a.kind.to_string(),
+                        "timeout": a.timeout_seconds,
+                        "command": a.command,
+                        "enabled": a.enabled,
+                    })).collect::<Vec<_>>(),
+                    "triggers": triggers,
+                })
+                .to_string()
+            } else {
+                let mut out = String::from("Actions:\n");
+                for a in &actions {
+                    out.push_str(&format!(
+                        "  {:<24} Kind={} Timeout={} Command=\"{}\"{}\n",
+                        a.name,
+                        a.kind,
+                        a.timeout_seconds,
+                        a.command,
+                        if a.enabled { "" } else { " (disabled)" }
+                    ));
+                }
+                if !triggers.is_empty() {
+                    out.push_str("\nTriggers (stasis trigger <name>):\n");
+                    for name in triggers {
+                        out.push_str(&format!("  {}\n", name));
                     }
                 }
+                out
+            };
+
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "action list", source })?;
+        }
+
+        "debug_env" => {
+            // Variables lock screens and notifiers commonly need, missing ones are
+            // the usual cause of commands that work in a terminal but not here
+            const SESSION_VARS: &[&str] = &[
+                "WAYLAND_DISPLAY",
+                "XDG_RUNTIME_DIR",
+                "DBUS_SESSION_BUS_ADDRESS",
+                "XDG_CURRENT_DESKTOP",
+                "XDG_SESSION_TYPE",
+                "PATH",
+                "HOME",
+            ];
+
+            let env = crate::actions::action_env();
+            let mut out = String::from("Environment passed to action commands:\n");
+            for (key, value) in &env {
+                out.push_str(&format!("  {}={}\n", key, value));
+            }
+
+            let missing: Vec<_> = SESSION_VARS
+                .iter()
+                .filter(|var| !env.iter().any(|(key, _)| key == *var))
+                .collect();
+            if !missing.is_empty() {
+                out.push_str("\nMissing session variables:\n");
+                for var in missing {
+                    out.push_str(&format!("  {}\n", var));
+                }
             }
+
+            stream
+                .write_all(out.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "environment", source })?;
         }
-    });
+
+        _ if cmd.starts_with("query ") => {
+            let field = cmd.trim_start_matches("query ").trim();
+            let idle = idle_timer.lock().await;
+            let value = match field {
+                "idle_seconds" => Some(idle.elapsed_idle().as_secs().to_string()),
+                "uptime_seconds" => Some(idle.start_time.elapsed().as_secs().to_string()),
+                "on_ac" => Some(idle.on_ac.to_string()),
+                "paused" => Some((idle.auto_inhibited() || idle.manually_paused).to_string()),
+                "inhibited" => {
+                    let app_blocking = app_inhibitor.lock().await.is_any_app_running().await;
+                    Some((idle.presentation || idle.auto_inhibited() || idle.manually_paused || app_blocking).to_string())
+                }
+                "battery_percent" => Some(idle.battery_percent.map(|p| p.to_string()).unwrap_or_default()),
+                "presentation" => Some(idle.presentation.to_string()),
+                "inhibit_remaining_seconds" => Some(
+                    idle.inhibit_remaining().map(|d| d.as_secs().to_string()).unwrap_or_default(),
+                ),
+                _ => None,
+            };
+
+            let response = match value {
+                Some(value) => format!("{}\n", value),
+                None => format!(
+                    "error: unknown field '{}', expected one of: {}\n",
+                    field,
+                    QUERY_FIELDS.join(", ")
+                ),
+            };
+            stream
+                .write_all(response.as_bytes())
+                .await
+                .map_err(|source| IpcError::Write { what: "query response", source })?;
+        }
+
+        "info" | "info --json" => {
+            let as_json = cmd.contains("--json");
+
+            let idle = idle_timer.lock().await;
+            let idle_time = idle.elapsed_idle();
+            let mut inhibitor = app_inhibitor.lock().await;
+            let app_blocking = inhibitor.is_any_app_running().await;
+            let fullscreen_inhibited = inhibitor.is_fullscreen_inhibited();
+            let idle_inhibited = idle.presentation || idle.auto_inhibited() || idle.manually_paused || app_blocking;
+            let uptime = idle.start_time.elapsed();
+            let action_states = idle.action_states();
+            let warning = idle.warning_status();
+
+            if as_json {
+                let (wayland_inhibited, respect_inhibitors, compositor_timeout_ms) = {
+                    let wl = wl_data.lock().await;
+                    (wl.is_inhibited(), wl.respect_inhibitors, wl.armed_timeout_ms)
+                };
+                let state = idle_state(&idle, fullscreen_inhibited, app_blocking, wayland_inhibited);
+
+                let actions: Vec<_> = action_states
+                    .iter()
+                    .filter_map(|(key, is_idle)| {
+                        idle.cfg.actions.get(key).map(|action| serde_json::json!({
+                            "key": key,
+                            "kind": action.kind.to_string(),
+                            "timeout": action.timeout_seconds,
+                            "is_idle": is_idle,
+                        }))
+                    })
+                    .collect();
+
+                let mut output = if idle_inhibited {
+                    serde_json::json!({
+                        "text": "☕",
+                        "tooltip": format!(
+                            "Idle inhibited\nIdle time: {}s\nUptime: {}s\nPaused: {}\nManually paused: {}\nAuto paused: {}\nApp blocking: {}",
+                            idle_time.as_secs(),
+                            uptime.as_secs(),
+                            idle.auto_inhibited(),
+                            idle.manually_paused,
+                            idle.auto_paused,
+                            app_blocking
+                        )
+                    })
+                } else {
+                    serde_json::json!({
+                        "text": "⌚",
+                        "tooltip": format!(
+                            "Idle active\nIdle time: {}s\nUptime: {}s\nPaused: {}\nManually paused: {}\nAuto paused: {}\nApp blocking: {}",
+                            idle_time.as_secs(),
+                            uptime.as_secs(),
+                            idle.auto_inhibited(),
+                            idle.manually_paused,
+                            idle.auto_paused,
+                            app_blocking
+                        )
+                    })
+                };
+                output["state"] = serde_json::Value::from(state);
+                output["warning"] = serde_json::json!(warning);
+                output["episode"] = serde_json::json!(idle.idle_episode());
+                output["presentation"] = serde_json::Value::from(idle.presentation);
+                output["fullscreen_inhibited"] = serde_json::Value::from(fullscreen_inhibited);
+                output["power_flips_last_hour"] = serde_json::json!(idle.power_flips_last_hour());
+                output["battery_percent"] = serde_json::json!(idle.battery_percent);
+                output["inhibit_remaining"] = serde_json::json!(idle.inhibit_remaining().map(|d| d.as_secs()));
+                output["compositor_timeout_ms"] = serde_json::json!(compositor_timeout_ms);
+                let next_action = idle.next_action();
+                if let Some((name, remaining)) = &next_action
+                    && let Some(tooltip) = output["tooltip"].as_str()
+                {
+                    output["tooltip"] = format!("{}\nNext action: {} in {}s", tooltip, name, remaining.as_secs()).into();
+                }
+                output["last_activity"] = serde_json::json!(idle.last_reset.map(|(source, at)| serde_json::json!({
+                    "source": source.to_string(),
+                    "seconds_ago": at.elapsed().as_secs(),
+                })));
+                output["next_action"] = serde_json::json!(next_action.map(|(name, remaining)| serde_json::json!({
+                    "key": name,
+                    "seconds": remaining.as_secs(),
+                })));
+                output["action_stats"] = serde_json::json!(idle.fire_counts);
+                output["respect_inhibitors"] = serde_json::Value::from(respect_inhibitors);
+                output["actions"] = serde_json::Value::Array(actions);
+
+                stream
+                    .write_all(output.to_string().as_bytes())
+                    .await
+                    .map_err(|source| IpcError::Write { what: "JSON info", source })?;
+            } else {
+                let stats = idle.cfg.pretty_print(Some(&config::RuntimeInfo {
+                    idle_time,
+                    uptime,
+                    is_inhibited: idle_inhibited,
+                    fullscreen_inhibited,
+                    auto_paused: idle.auto_paused,
+                    presentation: idle.presentation,
+                    action_states: &action_states,
+                    warning: warning.as_deref(),
+                    power_flips_last_hour: idle.power_flips_last_hour(),
+                    battery_percent: idle.battery_percent,
+                    inhibit_remaining: idle.inhibit_remaining(),
+                    compositor_timeout_ms: wl_data.lock().await.armed_timeout_ms,
+                    next_action: idle.next_action(),
+                    last_activity: idle.last_reset.map(|(source, at)| (source, at.elapsed())),
+                }));
+
+                stream
+                    .write_all(stats.as_bytes())
+                    .await
+                    .map_err(|source| IpcError::Write { what: "info", source })?;
+            }
+        }
+
+        _ => return Err(IpcError::UnknownCommand(cmd)),
+    }
+
+    Ok(())
+}
+
+/// `ac` or `battery`, marked when forced by `set_power`
+fn power_profile(timer: &IdleTimer) -> String {
+    let profile = if timer.on_ac { "ac" } else { "battery" };
+    if timer.forced_power.is_some() {
+        format!("{} (forced)", profile)
+    } else {
+        profile.to_string()
+    }
 }
 
+/// Machine-readable state for `info --json`. When several conditions hold the
+/// first match wins, in this order: `presentation`, `manually_paused`, `auto_paused`,
+/// `media_paused`, `screensaver_inhibited`, `lockfile_inhibited`, `fullscreen_inhibited`, `app_inhibited`,
+/// `wayland_inhibited`, then `active`.
+fn idle_state(
+    timer: &IdleTimer,
+    fullscreen_inhibited: bool,
+    app_blocking: bool,
+    wayland_inhibited: bool,
+) -> &'static str {
+    if timer.presentation {
+        "presentation"
+    } else if timer.manually_paused {
+        "manually_paused"
+    } else if timer.auto_paused {
+        "auto_paused"
+    } else if timer.media_playing {
+        "media_paused"
+    } else if timer.screensaver_inhibited {
+        "screensaver_inhibited"
+    } else if timer.lockfile_inhibited {
+        "lockfile_inhibited"
+    } else if fullscreen_inhibited {
+        "fullscreen_inhibited"
+    } else if app_blocking {
+        "app_inhibited"
+    } else if wayland_inhibited {
+        "wayland_inhibited"
+    } else {
+        "active"
+    }
+}
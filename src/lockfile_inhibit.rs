@@ -0,0 +1,37 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+
+use crate::idle_timer::{IdleTimer, InhibitSource};
+use crate::log::log_message;
+
+/// How often `inhibit_lockfile` is checked for
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pause idle actions while `idle.inhibit_lockfile` exists, so scripts can
+/// inhibit with `touch` and `rm` alone. The path is re-read from the config
+/// on every check, so reloads take effect without a restart.
+pub fn spawn_lockfile_inhibit(idle_timer: Arc<Mutex<IdleTimer>>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+
+            let mut timer = idle_timer.lock().await;
+            let path = timer.cfg.inhibit_lockfile.clone();
+            let exists = path.as_deref().is_some_and(|p| Path::new(p).exists());
+            if exists == timer.lockfile_inhibited {
+                continue;
+            }
+
+            if exists {
+                log_message(&format!("Inhibit lockfile {} exists, pausing idle", path.unwrap_or_default()));
+            } else {
+                log_message("Inhibit lockfile gone, lockfile inhibit cleared");
+            }
+            timer.set_inhibited(InhibitSource::Lockfile, exists);
+        }
+    });
+}
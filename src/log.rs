@@ -1,34 +1,87 @@
-use std::fs::{OpenOptions, create_dir_all, metadata, remove_file};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, create_dir_all, metadata, remove_file, rename};
 use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::PathBuf;
 use chrono::Local;
 use once_cell::sync::Lazy;
 use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 /// Maximum log file size in bytes before rotation (50 MB)
 const MAX_LOG_SIZE: u64 = 50 * 1024 * 1024;
 
+/// Log verbosity, each level includes the ones above it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            other => Err(format!("invalid log level '{}', expected error, warn, info, debug or trace", other)),
+        }
+    }
+}
+
 /// Global runtime config
 pub struct Config {
-    pub verbose: bool,
+    pub level: LogLevel,
 }
 
 pub static GLOBAL_CONFIG: Lazy<Mutex<Config>> = Lazy::new(|| {
     Mutex::new(Config {
-        verbose: false, // default
+        level: LogLevel::Info, // default
     })
 });
 
 /// Ensures session separator is only added once per program run
 static SESSION_SEPARATOR: Once = Once::new();
 
-pub fn set_verbose(enabled: bool) {
+pub fn set_log_level(level: LogLevel) {
     let mut config = GLOBAL_CONFIG.lock().unwrap();
-    config.verbose = enabled;
+    config.level = level;
+}
+
+fn enabled(level: LogLevel) -> bool {
+    GLOBAL_CONFIG.lock().unwrap().level >= level
+}
+
+/// Messages are echoed to the terminal from Debug up, as `--verbose` always did
+fn echo() -> bool {
+    enabled(LogLevel::Debug)
+}
+
+/// `idle.log_file`, None for the default under the cache dir
+static LOG_FILE: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+
+/// Send logs and command output to `path` from now on
+pub fn set_log_file(path: Option<PathBuf>) {
+    *LOG_FILE.lock().unwrap() = path;
 }
 
 /// Get log file path
 fn log_path() -> PathBuf {
+    if let Some(path) = LOG_FILE.lock().unwrap().clone() {
+        if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() && !dir.exists() {
+            let _ = create_dir_all(dir);
+        }
+        return path;
+    }
+
     let mut path = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
     path.push("stasis");
     if !path.exists() {
@@ -38,16 +91,30 @@ fn log_path() -> PathBuf {
     path
 }
 
-/// Rotate the log if too big
+/// Roll the log over to `<file>.1` if too big, replacing the previous one
 fn rotate_log_if_needed(path: &PathBuf) {
     if let Ok(meta) = metadata(path) {
         if meta.len() >= MAX_LOG_SIZE {
-            // Simple rotation: delete old log
-            let _ = remove_file(path);
+            let mut rolled = path.clone().into_os_string();
+            rolled.push(".1");
+            if rename(path, &rolled).is_err() {
+                let _ = remove_file(path);
+            }
         }
     }
 }
 
+/// Open the log for appending, rotating it first. Stasis's own messages and
+/// action command output both go through here. New files are user-only.
+pub fn open_log_file() -> std::io::Result<File> {
+    let path = log_path();
+    rotate_log_if_needed(&path);
+    // Add a separating blank line if file is not empty (only once per program run)
+    ensure_session_newline_once(&path);
+
+    OpenOptions::new().create(true).append(true).mode(0o600).open(&path)
+}
+
 /// Ensure newline is added only once per session, and only if file has content
 fn ensure_session_newline_once(path: &PathBuf) {
     SESSION_SEPARATOR.call_once(|| {
@@ -63,34 +130,104 @@ fn ensure_session_newline_once(path: &PathBuf) {
 }
 
 pub fn log_to_cache(message: &str) {
-    let path = log_path();    
-    // Rotate old log if too big
-    rotate_log_if_needed(&path);
-    // Add a separating blank line if file is not empty (only once per program run)
-    ensure_session_newline_once(&path);
-    
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&path)
-        .unwrap();
+    let Ok(mut file) = open_log_file() else {
+        return;
+    };
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
     let _ = writeln!(file, "[{}] {}", timestamp, message);
 }
 
 pub fn log_message(message: &str) {
+    if !enabled(LogLevel::Info) {
+        return;
+    }
     let msg = format!("[Stasis] {}", message);
     log_to_cache(&msg);
-    if GLOBAL_CONFIG.lock().unwrap().verbose {
+    if echo() {
         println!("{}", &msg);
     }
 }
 
+pub fn log_warn_message(message: &str) {
+    if !enabled(LogLevel::Warn) {
+        return;
+    }
+    let warn_msg = format!("[WARN] {}", message);
+    log_to_cache(&warn_msg);
+    if echo() {
+        eprintln!("{}", &warn_msg);
+    }
+}
+
+/// `idle.log_repeat_window_seconds`: an identical error is written at most once per window, 0 disables
+static ERROR_REPEAT_WINDOW: AtomicU64 = AtomicU64::new(60);
+
+/// Entries kept before errors outside the window are dropped
+const MAX_TRACKED_ERRORS: usize = 256;
+
+/// When each error message was last written and how often it was held back since
+static RECENT_ERRORS: Lazy<Mutex<HashMap<String, (Instant, u32)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn set_error_repeat_window(seconds: u64) {
+    ERROR_REPEAT_WINDOW.store(seconds, Ordering::Relaxed);
+}
+
+/// None if `message` was already written within the window, otherwise the
+/// number of copies held back since it last was
+fn error_repeats(message: &str) -> Option<u32> {
+    let window = Duration::from_secs(ERROR_REPEAT_WINDOW.load(Ordering::Relaxed));
+    if window.is_zero() {
+        return Some(0);
+    }
+
+    let mut recent = RECENT_ERRORS.lock().unwrap();
+    if let Some((logged_at, held_back)) = recent.get_mut(message) {
+        if logged_at.elapsed() < window {
+            *held_back += 1;
+            return None;
+        }
+        let repeats = *held_back;
+        *logged_at = Instant::now();
+        *held_back = 0;
+        return Some(repeats);
+    }
+
+    if recent.len() >= MAX_TRACKED_ERRORS {
+        recent.retain(|_, (logged_at, _)| logged_at.elapsed() < window);
+    }
+    recent.insert(message.to_string(), (Instant::now(), 0));
+    Some(0)
+}
+
+/// Identical errors are rate limited, see `set_error_repeat_window`
 pub fn log_error_message(message: &str) {
-    let error_msg = format!("[ERROR] {}", message);
+    let error_msg = match error_repeats(message) {
+        None => return,
+        Some(0) => format!("[ERROR] {}", message),
+        Some(n) => format!("[ERROR] {} (repeated {} times)", message, n),
+    };
     log_to_cache(&error_msg);
-    if GLOBAL_CONFIG.lock().unwrap().verbose {
+    if echo() {
         eprintln!("{}", &error_msg);
     }
 }
 
+/// Only written at Debug level or above (`--verbose`)
+pub fn log_debug_message(message: &str) {
+    if !enabled(LogLevel::Debug) {
+        return;
+    }
+    let debug_msg = format!("[DEBUG] {}", message);
+    log_to_cache(&debug_msg);
+    println!("{}", &debug_msg);
+}
+
+/// Per-event diagnostics, only written at Trace level
+pub fn log_trace_message(message: &str) {
+    if !enabled(LogLevel::Trace) {
+        return;
+    }
+    let trace_msg = format!("[TRACE] {}", message);
+    log_to_cache(&trace_msg);
+    println!("{}", &trace_msg);
+}
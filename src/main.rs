@@ -1,4 +1,4 @@
-use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+use std::{fs, path::{Path, PathBuf}, sync::{Arc, LazyLock}, time::Duration};
 
 use clap::{Parser, Subcommand};
 use eyre::Result;
@@ -11,17 +11,28 @@ mod actions;
 mod app_inhibit;
 mod brightness;
 mod config;
+mod config_watch;
+#[cfg(feature = "dbus_api")]
+mod dbus_api;
+mod desktop_settings;
+mod error;
 mod idle_timer;
 mod input;
 mod ipc;
+mod lockfile_inhibit;
 mod log;
 mod media;
+mod notify;
 mod power_detection;
+mod schedule;
+mod screensaver_inhibit;
 mod suspend;
+#[cfg(feature = "systemd")]
+mod systemd;
 mod utils;
 mod wayland;
 
-use log::{log_message, log_error_message, set_verbose};
+use log::{log_message, log_error_message, log_debug_message, set_log_file, set_log_level, LogLevel};
 use crate::wayland::{WaylandIdleData, setup as setup_wayland};
 
 #[derive(Parser, Debug)]
@@ -33,8 +44,12 @@ use crate::wayland::{WaylandIdleData, setup as setup_wayland};
 struct Args {
     #[arg(short, long, value_name = "FILE")]
     config: Option<PathBuf>,
-    #[arg(short, long, action)]
+    #[arg(short, long, action, help = "Log at debug level and echo logs to the terminal")]
     verbose: bool,
+    #[arg(long, value_name = "LEVEL", help = "error, warn, info, debug or trace (overrides -v and STASIS_LOG)")]
+    log_level: Option<LogLevel>,
+    #[arg(long, action, help = "Log idle actions instead of running them")]
+    dry_run: bool,
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -50,15 +65,57 @@ enum Commands {
     #[command(about = "Resume idle timers after a pause")]
     Resume,
 
+    #[command(about = "Pause only automatic timeout-driven actions; manual triggers still work")]
+    PauseAuto,
+
+    #[command(about = "Resume automatic actions after pause-auto")]
+    ResumeAuto,
+
     #[command(about = "Manually trigger idle actions")]
     TriggerIdle,
 
     #[command(about = "Trigger pre-suspend action manually")]
     TriggerPreSuspend,
 
+    #[command(about = "Run a named trigger from the config")]
+    Trigger {
+        #[arg(help = "Name of the trigger to run")]
+        name: String,
+    },
+
+    #[command(about = "Force-restore brightness captured before dimming")]
+    RestoreBrightness,
+
+    #[command(about = "Honor or ignore Wayland idle inhibitors held by apps, until next reload")]
+    SetRespectInhibitors {
+        #[arg(action = clap::ArgAction::Set, help = "true to honor inhibitors, false to ignore them")]
+        value: bool,
+    },
+
+    #[command(about = "Inhibit idle until `stasis resume`, or only for a while")]
+    Inhibit {
+        #[arg(value_parser = utils::parse_duration, help = "Resume automatically after this long, in seconds or e.g. 30m")]
+        duration: Option<std::time::Duration>,
+    },
+
     #[command(about = "Toggle manual idle inhibition (for Waybar etc.)")]
     ToggleInhibit,
 
+    #[command(about = "Turn presentation mode on or off, suspending all idle actions until turned off")]
+    Presentation {
+        #[arg(value_parser = clap::builder::BoolishValueParser::new(), help = "on or off")]
+        on: bool,
+    },
+
+    #[command(about = "Force the AC or battery profile, e.g. on a misdetected desktop, or go back to auto detection")]
+    SetPower {
+        #[arg(value_parser = ["ac", "battery", "auto"], help = "ac, battery or auto")]
+        profile: String,
+    },
+
+    #[command(about = "Print the active power profile, and whether it was forced")]
+    GetPower,
+
     #[command(about = "Stop the currently running instances of Stasis")]
     Stop,
 
@@ -67,129 +124,173 @@ enum Commands {
         #[arg(long, help = "Output as JSON (for Waybar or scripts)")]
         json: bool,
     },
+
+    #[command(about = "Print a single raw value for scripts, e.g. idle_seconds, uptime_seconds, on_ac, inhibited, paused")]
+    Query {
+        #[arg(help = "idle_seconds, uptime_seconds, on_ac, inhibited, paused, presentation, battery_percent or inhibit_remaining_seconds")]
+        field: String,
+    },
+
+    #[command(about = "Block until the system goes idle, or until --action fires while idle")]
+    WaitIdle {
+        #[arg(long, help = "Wait for this action (e.g. lock_screen) instead of any")]
+        action: Option<String>,
+        #[arg(long, value_parser = utils::parse_duration, help = "Give up after this long, in seconds or e.g. 30m, exiting with status 1")]
+        timeout: Option<std::time::Duration>,
+    },
+
+    #[command(about = "Show the environment the daemon passes to action commands")]
+    DebugEnv,
+
+    #[command(about = "List configured actions and triggers")]
+    ListActions {
+        #[arg(long, help = "Output as JSON")]
+        json: bool,
+    },
+
+    #[command(about = "Validate the configuration and exit, without starting the daemon")]
+    Check {
+        #[arg(value_name = "FILE", help = "Config to check (defaults to --config or the usual locations)")]
+        config: Option<PathBuf>,
+    },
 }
 
-const SOCKET_PATH: &str = "/tmp/stasis.sock";
+/// Control socket, one per compositor instance so separate sessions don't
+/// clobber each other's sockets. Clients outside a session (ssh, scripts)
+/// pick the only running instance if there is exactly one
+static SOCKET_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
+    match std::env::var("WAYLAND_DISPLAY").ok().as_deref().and_then(utils::wayland_instance) {
+        Some(instance) => PathBuf::from(format!("/tmp/stasis-{}.sock", instance)),
+        None => sole_instance_socket().unwrap_or_else(|| PathBuf::from("/tmp/stasis.sock")),
+    }
+});
+
+/// The single `/tmp/stasis-*.sock`, or None if there are none or several
+fn sole_instance_socket() -> Option<PathBuf> {
+    let mut sockets = fs::read_dir("/tmp").ok()?.flatten().map(|e| e.path()).filter(|p| {
+        p.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("stasis-") && n.ends_with(".sock"))
+    });
+    let first = sockets.next()?;
+    sockets.next().is_none().then_some(first)
+}
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Must be bound to wayland session,
-    // don't be naughty.
-    if std::env::var("WAYLAND_DISPLAY").is_err() {
-        eprintln!("Error: Wayland is not detected. Stasis requires Wayland to run.");
-        std::process::exit(1);
+    // --- Config check needs neither a session nor a running instance ---
+    if let Some(Commands::Check { config }) = &args.command {
+        std::process::exit(check_config(config.clone().or(args.config.clone())));
     }
 
     // --- Handle subcommands via socket ---
     if let Some(cmd) = &args.command {
-        use tokio::net::UnixStream;
-
-        match cmd {
-            Commands::Info { json } => {
-                if let Ok(mut stream) = UnixStream::connect(SOCKET_PATH).await {
-                    let msg = if *json { "info --json" } else { "info" };
-                    let _ = stream.write_all(msg.as_bytes()).await;
-
-                    let mut response = Vec::new();
-                    let _ = stream.read_to_end(&mut response).await;
-                    println!("{}", String::from_utf8_lossy(&response));
-                } else {
-                    // Waybar-friendly "Stasis not running"
-                    if *json {
-                        println!(r#"{{"text":"😴","tooltip":"Stasis is not running"}}"#);
-                    } else {
-                        println!("Stasis is not running");
-                    }
-                }
-            }
-            _ => {
-                let msg = match cmd {
-                    Commands::Reload => "reload",
-                    Commands::Pause => "pause",
-                    Commands::Resume => "resume",
-                    Commands::TriggerIdle => "trigger_idle",
-                    Commands::TriggerPreSuspend => "trigger_presuspend",
-                    Commands::ToggleInhibit => "toggle_inhibit",
-                    Commands::Stop => "stop",
-                    _ => unreachable!(),
-                };
-
-                if let Ok(mut stream) = UnixStream::connect(SOCKET_PATH).await {
-                    let _ = stream.write_all(msg.as_bytes()).await;
-
-                    if msg == "info" || msg == "toggle_inhibit" {
-                        let mut response = Vec::new();
-                        let _ = stream.read_to_end(&mut response).await;
-                        println!("{}", String::from_utf8_lossy(&response));
-                    }
-                } else {
-                    log_error_message("No running instance found");
-                }
-            }
-        }
+        std::process::exit(run_client(cmd, &SOCKET_PATH).await);
+    }
 
-        return Ok(());
+    // The daemon must be bound to a wayland session,
+    // don't be naughty. Client subcommands above work from any shell.
+    if std::env::var("WAYLAND_DISPLAY").ok().as_deref().and_then(utils::wayland_instance).is_none() {
+        eprintln!("Error: Wayland is not detected. Stasis requires Wayland to run.");
+        std::process::exit(1);
     }
 
+    // --- Log to the configured log_file from the first message on ---
+    let config_path = args.config.clone().map(Ok).unwrap_or_else(get_config_path)?;
+    set_log_file(config::read_log_file(&config_path));
+
     // --- Single instance enforcement ---
     let just_help_or_version = std::env::args().any(|a| matches!(a.as_str(), "-V" | "--version" | "-h" | "--help" | "help"));
-    if let Ok(_) = tokio::net::UnixStream::connect(SOCKET_PATH).await {
+    if let Ok(_) = tokio::net::UnixStream::connect(&*SOCKET_PATH).await {
         if !just_help_or_version {
             println!("Another instance of Stasis is already running.");
         }
         log_error_message("Another instance is already running.");
         return Ok(());
     }
-    let _ = fs::remove_file(SOCKET_PATH);
+    let _ = fs::remove_file(&*SOCKET_PATH);
 
-    let listener = UnixListener::bind(SOCKET_PATH).map_err(|_| {
+    let listener = UnixListener::bind(&*SOCKET_PATH).map_err(|_| {
         eyre::eyre!("Failed to bind control socket. Another instance may be running.")
     })?;
 
     setup_cleanup_handler();
 
     // --- Load config ---
-    let config_path = args.config.unwrap_or(get_config_path()?);
-    if args.verbose {
-        log_message("Verbose mode enabled");
-        set_verbose(true);
+    let env_level = std::env::var("STASIS_LOG").ok().and_then(|v| match v.parse::<LogLevel>() {
+        Ok(level) => Some(level),
+        Err(e) => {
+            eprintln!("Ignoring STASIS_LOG: {}", e);
+            None
+        }
+    });
+    let level = args
+        .log_level
+        .or(args.verbose.then_some(LogLevel::Debug))
+        .or(env_level)
+        .unwrap_or(LogLevel::Info);
+    set_log_level(level);
+    if level >= LogLevel::Debug {
+        log_message(&format!("Logging at {:?} level", level));
+    }
+    if args.dry_run {
+        log_message("Dry-run mode enabled, commands will be logged but not run");
+        actions::set_dry_run(true);
     }
-    let cfg = Arc::new(config::load_config(config_path.to_str().unwrap())?);
+    let cfg = Arc::new(config::load_config(&config_path)?);
     let idle_timer = Arc::new(Mutex::new(idle_timer::IdleTimer::new(&cfg)));
     idle_timer.lock().await.init().await;
 
+    // --- Check the backlight is writable before brightness actions rely on it ---
+    if cfg.actions.values().any(|a| a.kind == config::IdleActionKind::Brightness) {
+        brightness::probe_backlight_access();
+    }
+
     // --- Spawn background tasks ---
     idle_timer::spawn_idle_task(Arc::clone(&idle_timer)).await;
     input::spawn_input_task(Arc::clone(&idle_timer));
 
+    // --- Follow desktop environment idle-delay ---
+    if cfg.follow_de_idle_delay.is_some() {
+        desktop_settings::spawn_de_idle_delay_watcher(Arc::clone(&idle_timer));
+    }
+
+    // --- D-Bus state signals ---
+    #[cfg(feature = "dbus_api")]
+    dbus_api::spawn_dbus_api(Arc::clone(&idle_timer));
+
+    // --- D-Bus ScreenSaver inhibitors ---
+    if cfg.screensaver_inhibit {
+        screensaver_inhibit::spawn_screensaver_inhibit(Arc::clone(&idle_timer));
+    }
+
+    // --- Inhibit while a lockfile exists ---
+    lockfile_inhibit::spawn_lockfile_inhibit(Arc::clone(&idle_timer));
+
     // --- Spawn suspend event listener ---
     let lid_idle_timer = Arc::clone(&idle_timer);
-    tokio::spawn(async move {
-        if let Err(e) = suspend::listen_for_suspend_events(lid_idle_timer).await {
-            log_error_message(&format!("D-Bus suspend event listener failed: {}", e));
-        }
-    });
+    tokio::spawn(suspend::listen_for_suspend_events(lid_idle_timer));
 
     // AC/Battery Detection
     let idle_clone = Arc::clone(&idle_timer);
     tokio::spawn(async move {
         // Detect laptop or desktop
-        let is_laptop = crate::utils::is_laptop();
+        let chassis = crate::utils::chassis();
+        let is_laptop = chassis.is_laptop();
 
         // Detect initial power state and log it
-        let last_on_ac = crate::power_detection::detect_initial_power_state(is_laptop);
+        let on_ac = crate::power_detection::detect_initial_power_state(chassis);
 
         // Set initial state in IdleTimer
         {
             let mut timer = idle_clone.lock().await;
-            timer.on_ac = last_on_ac;
+            timer.on_ac = on_ac;
         }
 
         // Poll every 5 seconds (or any interval you want)
         let mut ticker = tokio::time::interval(Duration::from_secs(5));
-        let mut last_on_ac = last_on_ac;
         loop {
             ticker.tick().await;
 
@@ -198,17 +299,32 @@ async fn main() -> Result<()> {
                 continue;
             }
 
-            // Check current AC state
+            // Check current AC state and charge
             let on_ac = crate::power_detection::is_on_ac_power(is_laptop);
+            let capacity = crate::power_detection::battery_capacity();
+            log_debug_message(&format!(
+                "Power reading: {} ({})",
+                if on_ac { "AC" } else { "Battery" },
+                capacity.map(|p| format!("{}%", p)).unwrap_or_else(|| "no battery".to_string())
+            ));
+
+            // Compare against the timer's state, `set-power` and `auto` change it too
+            let mut timer = idle_clone.lock().await;
+
+            // `stasis set-power` holds the profile until set back to auto
+            if timer.forced_power.is_some() {
+                continue;
+            }
 
             // Only update if state changed
-            if on_ac != last_on_ac {
-                last_on_ac = on_ac;
+            if on_ac != timer.on_ac {
                 log_message(&format!("Power source changed: {}", if on_ac { "AC" } else { "Battery" }));
 
                 // Update IdleTimer
-                idle_clone.lock().await.update_power_source(on_ac).await;
+                timer.update_power_source(on_ac).await;
             }
+
+            timer.update_battery_level(capacity).await;
         }
     });
 
@@ -220,12 +336,30 @@ async fn main() -> Result<()> {
 
     // --- Wayland setup ---
     let wl_data = setup_wayland(Arc::clone(&idle_timer), cfg.respect_idle_inhibitors).await?;
+    {
+        let wl = wl_data.lock().await;
+        if wl.toplevel_manager.is_some() {
+            app_inhibitor.lock().await.set_toplevel_apps(wl.toplevel_apps.clone());
+        }
+    }
+
+    // --- Reloads from the socket and the watcher are applied here ---
+    let reloader = ipc::spawn_reload_task(Arc::clone(&idle_timer), Arc::clone(&wl_data), config_path.clone());
+
+    // --- Auto-reload on config change ---
+    if cfg.auto_reload
+        && let Some(handle) = config_watch::spawn_config_watcher(config_path.clone(), reloader.clone())
+    {
+        idle_timer.lock().await.set_config_watcher(handle);
+    }
 
     // --- Control socket ---  
     ipc::spawn_control_socket_with_listener(
         Arc::clone(&idle_timer),
         Arc::clone(&app_inhibitor),
-        config_path.to_str().unwrap().to_string(),
+        Arc::clone(&wl_data),
+        config_path,
+        reloader,
         listener,
     ).await;
 
@@ -240,9 +374,12 @@ async fn main() -> Result<()> {
     let local = LocalSet::new();
     local.run_until(async {
         if cfg.monitor_media {
-            media::spawn_media_monitor(Arc::clone(&idle_timer))?;
+            let handle = media::spawn_media_monitor(Arc::clone(&idle_timer))?;
+            idle_timer.lock().await.set_media_monitor(handle);
         }
         log_message(&format!("Running. Idle actions loaded: {}", cfg.actions.len()));
+        #[cfg(feature = "systemd")]
+        systemd::notify_ready();
         std::future::pending::<()>().await;
         #[allow(unreachable_code)]
         Ok::<(), eyre::Report>(())
@@ -261,17 +398,241 @@ fn setup_cleanup_handler() {
     }
 
     let _ = ctrlc::set_handler(move || {
-        let _ = fs::remove_file(SOCKET_PATH);
+        let _ = fs::remove_file(&*SOCKET_PATH);
         std::process::exit(0);
     });
 
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
-        let _ = fs::remove_file(SOCKET_PATH);
+        let _ = fs::remove_file(&*SOCKET_PATH);
         default_panic(panic_info);
     }));
 }
 
+/// Send a client subcommand to the instance listening on `socket`, returning
+/// the exit status. Needs no Wayland session, so it works from ssh or scripts too
+async fn run_client(cmd: &Commands, socket: &Path) -> i32 {
+    use tokio::net::UnixStream;
+
+    match cmd {
+        Commands::Info { json } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = if *json { "info --json" } else { "info" };
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                println!("{}", String::from_utf8_lossy(&response));
+            } else {
+                // Waybar-friendly "Stasis not running"
+                if *json {
+                    println!(r#"{{"text":"😴","tooltip":"Stasis is not running"}}"#);
+                } else {
+                    println!("Stasis is not running");
+                }
+            }
+        }
+        Commands::ListActions { json } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = if *json { "list_actions --json" } else { "list_actions" };
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                println!("{}", String::from_utf8_lossy(&response));
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+        Commands::WaitIdle { action, timeout } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = match action {
+                    Some(action) => format!("subscribe_idle {}", action),
+                    None => "subscribe_idle".to_string(),
+                };
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let read = stream.read_to_end(&mut response);
+                let completed = match timeout {
+                    Some(timeout) => tokio::time::timeout(*timeout, read).await.is_ok(),
+                    None => {
+                        let _ = read.await;
+                        true
+                    }
+                };
+
+                if !completed {
+                    eprintln!("Timed out waiting for idle");
+                    return 1;
+                }
+                if response.is_empty() {
+                    eprintln!("Stasis stopped before going idle");
+                    return 1;
+                }
+                print!("{}", String::from_utf8_lossy(&response));
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+        Commands::Trigger { name } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = format!("trigger {}", name);
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                println!("{}", String::from_utf8_lossy(&response));
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+        Commands::Inhibit { duration } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = match duration {
+                    Some(duration) => format!("inhibit {}", duration.as_secs().max(1)),
+                    None => "inhibit".to_string(),
+                };
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                println!("{}", String::from_utf8_lossy(&response));
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+        Commands::Query { field } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = format!("query {}", field);
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                let response = String::from_utf8_lossy(&response);
+                if let Some(error) = response.strip_prefix("error: ") {
+                    eprint!("{}", error);
+                    return 1;
+                }
+                print!("{}", response);
+            } else {
+                log_error_message("No running instance found");
+                return 1;
+            }
+        }
+        Commands::Presentation { on } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = format!("presentation {}", if *on { "on" } else { "off" });
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                println!("{}", String::from_utf8_lossy(&response));
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+        Commands::SetPower { profile } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = format!("set_power {}", profile);
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                println!("{}", String::from_utf8_lossy(&response));
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+        Commands::SetRespectInhibitors { value } => {
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let msg = format!("set_respect_inhibitors {}", value);
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                let mut response = Vec::new();
+                let _ = stream.read_to_end(&mut response).await;
+                println!("{}", String::from_utf8_lossy(&response));
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+        _ => {
+            let msg = match cmd {
+                Commands::Reload => "reload",
+                Commands::Pause => "pause",
+                Commands::Resume => "resume",
+                Commands::PauseAuto => "pause_auto",
+                Commands::ResumeAuto => "resume_auto",
+                Commands::TriggerIdle => "trigger_idle",
+                Commands::TriggerPreSuspend => "trigger_presuspend",
+                Commands::RestoreBrightness => "restore_brightness",
+                Commands::ToggleInhibit => "toggle_inhibit",
+                Commands::Stop => "stop",
+                Commands::DebugEnv => "debug_env",
+                Commands::GetPower => "get_power",
+                _ => unreachable!(),
+            };
+
+            if let Ok(mut stream) = UnixStream::connect(socket).await {
+                let _ = stream.write_all(msg.as_bytes()).await;
+
+                if msg == "info" || msg == "toggle_inhibit" || msg == "reload" || msg == "debug_env" || msg == "get_power" {
+                    let mut response = Vec::new();
+                    let _ = stream.read_to_end(&mut response).await;
+                    println!("{}", String::from_utf8_lossy(&response));
+                }
+            } else {
+                log_error_message("No running instance found");
+            }
+        }
+    }
+
+    0
+}
+
+/// Load a config the way the daemon would and report every warning,
+/// returning the exit status for `stasis check`
+fn check_config(path: Option<PathBuf>) -> i32 {
+    let path = match path.map(Ok).unwrap_or_else(get_config_path) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+
+    // Only the report below should reach the terminal or the log file
+    set_log_level(LogLevel::Error);
+
+    let cfg = match config::load_config(&path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("{}: failed to parse: {}", path.display(), e);
+            return 1;
+        }
+    };
+
+    for warning in &cfg.warnings {
+        println!("{}: {}", path.display(), warning);
+    }
+
+    // A program missing from PATH may still be found at runtime, only errors fail the check
+    let errors = cfg.warnings.iter().filter(|w| w.is_error()).count();
+    if errors > 0 {
+        println!("{} error(s), {} warning(s) found", errors, cfg.warnings.len() - errors);
+        return 1;
+    }
+
+    println!(
+        "{}: OK ({} actions, {} triggers, {} warning(s))",
+        path.display(),
+        cfg.actions.len(),
+        cfg.triggers.len(),
+        cfg.warnings.len()
+    );
+    0
+}
+
 /// Determine default config path
 fn get_config_path() -> Result<PathBuf> {
     if let Some(mut path) = dirs::home_dir() {
@@ -305,6 +666,8 @@ async fn setup_shutdown_handler(
                 _ = sigint.recv() => log_message("Received SIGINT, shutting down..."),
                 _ = sigterm.recv() => log_message("Received SIGTERM, shutting down..."),
             }
+            #[cfg(feature = "systemd")]
+            systemd::notify_stopping();
 
             // Shutdown idle timer
             idle_timer.lock().await.shutdown().await;
@@ -319,9 +682,61 @@ async fn setup_shutdown_handler(
             };
             shutdown_notify.notify_waiters();
 
-            let _ = std::fs::remove_file(SOCKET_PATH);
+            let _ = std::fs::remove_file(&*SOCKET_PATH);
             std::process::exit(0);
         }
     });
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accept one client on `socket`, reply with `reply` and hand back what it sent
+    fn fake_instance(socket: &Path, reply: &'static str) -> tokio::task::JoinHandle<String> {
+        let _ = fs::remove_file(socket);
+        let listener = UnixListener::bind(socket).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        })
+    }
+
+    fn command(args: &[&str]) -> Commands {
+        Args::try_parse_from(std::iter::once("stasis").chain(args.iter().copied()))
+            .unwrap()
+            .command
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn client_subcommands_reach_the_instance_socket() {
+        let socket = std::env::temp_dir().join(format!("stasis-test-client-{}.sock", std::process::id()));
+
+        let sent = fake_instance(&socket, "Triggered lock");
+        assert_eq!(run_client(&command(&["trigger", "lock"]), &socket).await, 0);
+        assert_eq!(sent.await.unwrap(), "trigger lock");
+
+        let sent = fake_instance(&socket, "error: unknown field\n");
+        assert_eq!(run_client(&command(&["query", "bogus"]), &socket).await, 1);
+        assert_eq!(sent.await.unwrap(), "query bogus");
+
+        let _ = fs::remove_file(&socket);
+
+        // Nothing listening: reported, not a panic or a hang
+        assert_eq!(run_client(&command(&["query", "state"]), &socket).await, 1);
+    }
+
+    #[test]
+    fn check_needs_no_session_or_instance() {
+        let path = std::env::temp_dir().join(format!("stasis-check-{}.rune", std::process::id()));
+        fs::write(&path, "idle:\n  notify_me:\n    timeout 60\n    command \"true\"\n  end\nend\n").unwrap();
+        let status = check_config(Some(path.clone()));
+        let _ = fs::remove_file(&path);
+        assert_eq!(status, 0);
+    }
+}
@@ -1,52 +1,291 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 use eyre::Result;
+use futures::StreamExt;
 use mpris::{PlayerFinder, PlaybackStatus};
-use tokio::{task, time};
-use crate::idle_timer::IdleTimer;
-use crate::log::log_error_message;
+use tokio::{sync::Notify, task::{self, JoinHandle}, time};
+use zbus::{Connection, Proxy, fdo::DBusProxy, proxy::{Builder, CacheProperties}};
+use crate::config::AppPattern;
+use crate::idle_timer::{IdleTimer, InhibitSource};
+use crate::log::{log_debug_message, log_error_message, log_message};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// Snapshot of a player taken from the mpris crate
+struct PlayerInfo {
+    bus_name: String,
+    player_name: String,
+    identity: String,
+    playing: bool,
+}
+
+/// Aborts the player watcher along with the monitor task that owns it
+struct WatcherGuard(JoinHandle<()>);
+
+impl Drop for WatcherGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
 
 /// Setup MPRIS monitoring using a Tokio task
-pub fn spawn_media_monitor(idle_timer: Arc<tokio::sync::Mutex<IdleTimer>>) -> Result<()> {
+pub fn spawn_media_monitor(idle_timer: Arc<tokio::sync::Mutex<IdleTimer>>) -> Result<JoinHandle<()>> {
     let idle_timer_clone = Arc::clone(&idle_timer);
-    let interval = Duration::from_secs(2);
 
-    task::spawn(async move {
-        let mut ticker = time::interval(interval);
+    let handle = task::spawn(async move {
+        // Wake the monitor as soon as a player appears or disappears on the bus
+        let players_changed = Arc::new(Notify::new());
+        let _watcher = WatcherGuard(spawn_player_watcher(Arc::clone(&players_changed)));
+
         let mut media_playing = false;
+        let mut ignored_players: HashSet<String> = HashSet::new();
+        let mut known_players: HashSet<String> = HashSet::new();
+
+        // Used to double-check playback status when the mpris crate reports stale data
+        let connection = match Connection::session().await {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                log_error_message(&format!("MPRIS: failed to connect to session bus: {}", e));
+                None
+            }
+        };
 
         loop {
-            ticker.tick().await;
-
-            // Check media players fresh each tick
-            let any_playing = match PlayerFinder::new() {
-                Ok(finder) => match finder.find_all() {
-                    Ok(players) => players.iter().any(|player| {
-                        player.get_playback_status()
-                            .map(|s| s == PlaybackStatus::Playing)
-                            .unwrap_or(false)
-                    }),
-                    Err(e) => {
-                        log_error_message(&format!("MPRIS: failed to list players: {:?}", e));
-                        false
+            // Read media settings fresh so reloads take effect
+            let (ignore_remote, remote_players, inhibit_players, ignore_players, require_audio, poll_seconds) = {
+                let timer = idle_timer_clone.lock().await;
+                (
+                    timer.cfg.ignore_remote_media,
+                    timer.cfg.remote_media_players.clone(),
+                    timer.cfg.media_inhibit_players.clone(),
+                    timer.cfg.media_ignore_players.clone(),
+                    timer.cfg.media_require_audio,
+                    timer.cfg.media_poll_seconds,
+                )
+            };
+
+            let players = scan_players();
+
+            for player in &players {
+                if known_players.insert(player.bus_name.clone()) {
+                    log_debug_message(&format!(
+                        "MPRIS: discovered player '{}' ({})",
+                        player.identity, player.bus_name
+                    ));
+                }
+            }
+            known_players.retain(|name| players.iter().any(|p| &p.bus_name == name));
+
+            let mut playing_player = None;
+            for player in &players {
+                if ignore_remote && is_remote_player(player, &remote_players) {
+                    // Only log the first time we see this player
+                    if ignored_players.insert(player.bus_name.clone()) {
+                        log_message(&format!(
+                            "MPRIS: ignoring remote player '{}' ({})",
+                            player.identity, player.bus_name
+                        ));
                     }
-                },
-                Err(e) => {
-                    log_error_message(&format!("MPRIS: failed to create finder: {:?}", e));
-                    false
+                    continue;
                 }
-            };
+
+                let filtered_out = (!inhibit_players.is_empty() && !matches_player(player, &inhibit_players))
+                    || matches_player(player, &ignore_players);
+                if filtered_out {
+                    if ignored_players.insert(player.bus_name.clone()) {
+                        log_message(&format!(
+                            "MPRIS: player '{}' ({}) filtered out by media_inhibit_players/media_ignore_players",
+                            player.identity, player.bus_name
+                        ));
+                    }
+                    continue;
+                }
+
+                let playing = player.playing || match &connection {
+                    Some(conn) => query_playback_status(conn, &player.bus_name).await,
+                    None => false,
+                };
+
+                if playing {
+                    playing_player = Some(player);
+                    break;
+                }
+            }
+            // A "Playing" status alone can be a paused or silent tab
+            if require_audio && playing_player.is_some() && !audio_is_playing().await {
+                log_debug_message("MPRIS: a player reports Playing but no audio is being output");
+                playing_player = None;
+            }
+            let any_playing = playing_player.is_some();
 
             // Pause or resume idle timer based on media playback
-            let mut timer = idle_timer_clone.lock().await;
-            if any_playing && !media_playing {
-                timer.pause(false);
-                media_playing = true;
-            } else if !any_playing && media_playing {
-                timer.resume(false);
-                media_playing = false;
+            {
+                let mut timer = idle_timer_clone.lock().await;
+                if let Some(player) = playing_player && !media_playing {
+                    log_message(&format!("MPRIS: '{}' ({}) is playing, pausing idle", player.identity, player.bus_name));
+                    media_playing = true;
+                } else if !any_playing && media_playing {
+                    media_playing = false;
+                }
+                timer.set_inhibited(InhibitSource::Media, media_playing);
+            }
+
+            tokio::select! {
+                _ = time::sleep(Duration::from_secs(poll_seconds)) => {}
+                _ = players_changed.notified() => {}
             }
         }
     });
 
+    Ok(handle)
+}
+
+/// List players via the mpris crate. Players are dropped before returning
+/// so the caller's future stays `Send`.
+fn scan_players() -> Vec<PlayerInfo> {
+    let finder = match PlayerFinder::new() {
+        Ok(finder) => finder,
+        Err(e) => {
+            log_error_message(&format!("MPRIS: failed to create finder: {:?}", e));
+            return Vec::new();
+        }
+    };
+
+    match finder.find_all() {
+        Ok(players) => players
+            .iter()
+            .map(|player| PlayerInfo {
+                bus_name: player.bus_name().to_string(),
+                player_name: player.bus_name_player_name_part().to_string(),
+                identity: player.identity().to_string(),
+                playing: player.get_playback_status()
+                    .map(|s| s == PlaybackStatus::Playing)
+                    .unwrap_or(false),
+            })
+            .collect(),
+        Err(e) => {
+            log_error_message(&format!("MPRIS: failed to list players: {:?}", e));
+            Vec::new()
+        }
+    }
+}
+
+/// Read `PlaybackStatus` directly over D-Bus, bypassing any property cache
+async fn query_playback_status(connection: &Connection, bus_name: &str) -> bool {
+    let proxy = match Builder::<Proxy>::new(connection)
+        .destination(bus_name.to_string())
+        .and_then(|b| b.path("/org/mpris/MediaPlayer2"))
+        .and_then(|b| b.interface("org.mpris.MediaPlayer2.Player"))
+    {
+        Ok(builder) => builder.cache_properties(CacheProperties::No).build().await,
+        Err(e) => Err(e),
+    };
+
+    match proxy {
+        Ok(proxy) => proxy
+            .get_property::<String>("PlaybackStatus")
+            .await
+            .map(|status| status == "Playing")
+            .unwrap_or(false),
+        Err(e) => {
+            log_debug_message(&format!("MPRIS: failed to query {}: {}", bus_name, e));
+            false
+        }
+    }
+}
+
+/// True if any PulseAudio/PipeWire sink input is running, unmuted and above
+/// zero volume, per `pactl list sink-inputs`. When pactl can't be run this
+/// returns true so media inhibition falls back to the MPRIS status alone.
+async fn audio_is_playing() -> bool {
+    let output = tokio::process::Command::new("pactl")
+        .args(["list", "sink-inputs"])
+        .env("LC_ALL", "C")
+        .output()
+        .await;
+
+    match output {
+        Ok(output) if output.status.success() => {
+            any_audible_sink_input(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => {
+            log_debug_message(&format!("MPRIS: pactl failed with {}, ignoring media_require_audio", output.status));
+            true
+        }
+        Err(e) => {
+            log_debug_message(&format!("MPRIS: could not run pactl ({}), ignoring media_require_audio", e));
+            true
+        }
+    }
+}
+
+fn any_audible_sink_input(listing: &str) -> bool {
+    listing.split("Sink Input #").skip(1).any(|block| {
+        let mut corked = false;
+        let mut muted = false;
+        let mut silent = false;
+        for line in block.lines().map(str::trim) {
+            if let Some(value) = line.strip_prefix("Corked:") {
+                corked = value.trim() == "yes";
+            } else if let Some(value) = line.strip_prefix("Mute:") {
+                muted = value.trim() == "yes";
+            } else if let Some(value) = line.strip_prefix("Volume:") {
+                // e.g. "front-left: 0 /   0% / -inf dB,   front-right: 0 /   0% / -inf dB"
+                silent = value
+                    .split_whitespace()
+                    .filter(|token| token.ends_with('%'))
+                    .all(|token| token == "0%");
+            }
+        }
+        !corked && !muted && !silent
+    })
+}
+
+/// Subscribe to NameOwnerChanged so players registered after startup are picked up immediately
+fn spawn_player_watcher(players_changed: Arc<Notify>) -> JoinHandle<()> {
+    task::spawn(async move {
+        if let Err(e) = watch_player_names(players_changed).await {
+            log_error_message(&format!("MPRIS: player watcher failed: {}", e));
+        }
+    })
+}
+
+async fn watch_player_names(players_changed: Arc<Notify>) -> zbus::Result<()> {
+    let connection = Connection::session().await?;
+    let dbus = DBusProxy::new(&connection).await?;
+    let mut stream = dbus.receive_name_owner_changed().await?;
+
+    while let Some(signal) = stream.next().await {
+        let args = signal.args()?;
+        let name = args.name().to_string();
+        if !name.starts_with(MPRIS_PREFIX) {
+            continue;
+        }
+
+        if args.new_owner().is_some() {
+            log_debug_message(&format!("MPRIS: player appeared on bus: {}", name));
+        } else {
+            log_debug_message(&format!("MPRIS: player left bus: {}", name));
+        }
+        players_changed.notify_one();
+    }
+
     Ok(())
 }
+
+/// Returns true if a pattern matches the player's identity, its bus name or
+/// the player part of it (`spotify` in `org.mpris.MediaPlayer2.spotify`)
+fn matches_player(player: &PlayerInfo, patterns: &[AppPattern]) -> bool {
+    patterns.iter().any(|p| {
+        p.is_match(&player.identity) || p.is_match(&player.player_name) || p.is_match(&player.bus_name)
+    })
+}
+
+/// Returns true if the player's identity or bus name matches the remote list
+fn is_remote_player(player: &PlayerInfo, remote_players: &[String]) -> bool {
+    let identity = player.identity.to_lowercase();
+    let bus_name = player.player_name.to_lowercase();
+
+    remote_players
+        .iter()
+        .any(|name| identity.contains(name.as_str()) || bus_name.contains(name.as_str()))
+}
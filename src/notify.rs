@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use zbus::{zvariant::Value, Connection, Proxy};
+
+use crate::log::log_error_message;
+
+/// Urgency hint from the desktop notifications spec
+#[derive(Debug, Clone, Copy)]
+pub enum Urgency {
+    #[allow(dead_code)]
+    Low = 0,
+    Normal = 1,
+    /// Stays on screen until dismissed
+    Critical = 2,
+}
+
+/// Send a notification through `org.freedesktop.Notifications`
+pub async fn send(summary: &str, body: &str, urgency: Urgency) -> zbus::Result<()> {
+    let connection = Connection::session().await?;
+    let proxy = Proxy::new(
+        &connection,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+    ).await?;
+
+    let mut hints: HashMap<&str, Value> = HashMap::new();
+    hints.insert("urgency", Value::U8(urgency as u8));
+    // Critical notifications never expire, the rest use the server default
+    let expire_timeout: i32 = match urgency {
+        Urgency::Critical => 0,
+        _ => -1,
+    };
+
+    let _: u32 = proxy
+        .call(
+            "Notify",
+            &("stasis", 0u32, "", summary, body, Vec::<&str>::new(), hints, expire_timeout),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Fire-and-forget `send` that logs failures, for use from sync code
+pub fn spawn_notification(summary: String, body: String, urgency: Urgency) {
+    tokio::spawn(async move {
+        if let Err(e) = send(&summary, &body, urgency).await {
+            log_error_message(&format!("Failed to send notification: {}", e));
+        }
+    });
+}
@@ -1,15 +1,24 @@
 use std::fs;
+use std::path::Path;
 use crate::log::log_message;
+use crate::utils::Chassis;
 
 /// Detect initial power state on laptop (called once at startup)
-pub fn detect_initial_power_state(is_laptop: bool) -> bool {
-    if !is_laptop {
-        log_message("Desktop detected, skipping power source check");
-        return true;
+pub fn detect_initial_power_state(chassis: Chassis) -> bool {
+    match chassis {
+        Chassis::Laptop => {}
+        Chassis::Desktop => {
+            log_message("Desktop detected, skipping power source check");
+            return true;
+        }
+        Chassis::Unknown => {
+            log_message("Unknown chassis and no battery found, assuming desktop");
+            return true;
+        }
     }
 
     // Simply check AC adapters once
-    let on_ac = is_on_ac_power(is_laptop);
+    let on_ac = is_on_ac_power(true);
     log_message(&format!("Initial power detection: {}", if on_ac { "AC" } else { "Battery" }));
     on_ac
 }
@@ -19,9 +28,12 @@ pub fn is_on_ac_power(is_laptop: bool) -> bool {
     if !is_laptop {
         return true;
     }
+    ac_online(Path::new("/sys/class/power_supply"))
+}
 
-    // Scan /sys/class/power_supply
-    if let Ok(entries) = fs::read_dir("/sys/class/power_supply/") {
+/// Whether any mains adapter under the `power_supply` directory is online
+fn ac_online(power_supply: &Path) -> bool {
+    if let Ok(entries) = fs::read_dir(power_supply) {
         for entry in entries.filter_map(|e| e.ok()) {
             let path = entry.path();
             let name = path.file_name().unwrap_or_default().to_string_lossy();
@@ -52,3 +64,70 @@ pub fn is_on_ac_power(is_laptop: bool) -> bool {
     // If no AC detected, assume battery
     false
 }
+
+/// Average charge of all batteries in percent, None when there are none
+pub fn battery_capacity() -> Option<u8> {
+    battery_capacity_in(Path::new("/sys/class/power_supply"))
+}
+
+fn battery_capacity_in(power_supply: &Path) -> Option<u8> {
+    let entries = fs::read_dir(power_supply).ok()?;
+    let readings: Vec<u32> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| {
+            fs::read_to_string(path.join("type"))
+                .map(|t| t.trim() == "Battery")
+                .unwrap_or(false)
+        })
+        .filter_map(|path| fs::read_to_string(path.join("capacity")).ok()?.trim().parse().ok())
+        .collect();
+
+    if readings.is_empty() {
+        return None;
+    }
+    Some((readings.iter().sum::<u32>() / readings.len() as u32).min(100) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::sysfs;
+
+    #[test]
+    fn mains_or_legacy_adapter_online_means_ac() {
+        let mains = sysfs("mains", &[("AC/type", "Mains\n"), ("AC/online", "1\n"), ("BAT0/type", "Battery\n")]);
+        assert!(ac_online(&mains));
+
+        let unplugged = sysfs("unplugged", &[("ADP1/type", "Mains\n"), ("ADP1/online", "0\n")]);
+        assert!(!ac_online(&unplugged));
+
+        // Older drivers leave out `type`, the adapter name gives it away
+        let legacy = sysfs("legacy", &[("ACAD/online", "1\n")]);
+        assert!(ac_online(&legacy));
+
+        for root in [mains, unplugged, legacy] {
+            let _ = fs::remove_dir_all(root);
+        }
+    }
+
+    #[test]
+    fn battery_capacity_averages_batteries_only() {
+        let root = sysfs(
+            "capacity",
+            &[
+                ("BAT0/type", "Battery\n"),
+                ("BAT0/capacity", "80\n"),
+                ("BAT1/type", "Battery\n"),
+                ("BAT1/capacity", "40\n"),
+                ("AC/type", "Mains\n"),
+            ],
+        );
+        assert_eq!(battery_capacity_in(&root), Some(60));
+        let _ = fs::remove_dir_all(&root);
+
+        let empty = sysfs("no-battery", &[("AC/type", "Mains\n")]);
+        assert_eq!(battery_capacity_in(&empty), None);
+        let _ = fs::remove_dir_all(&empty);
+    }
+}
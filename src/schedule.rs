@@ -0,0 +1,62 @@
+use chrono::{DateTime, Datelike, Local, NaiveTime, Weekday};
+
+/// A schedule entry scaling action timeouts on matching days/times
+#[derive(Debug, Clone)]
+pub struct ScheduleEntry {
+    pub name: String,
+    /// Empty means every day
+    pub days: Vec<Weekday>,
+    /// Optional local time window, may wrap past midnight (e.g. 22:00-06:00)
+    pub window: Option<(NaiveTime, NaiveTime)>,
+    pub multiplier: f64,
+}
+
+impl ScheduleEntry {
+    pub fn matches(&self, now: DateTime<Local>) -> bool {
+        let time = now.time();
+        let mut day = now.weekday();
+
+        if let Some((start, end)) = self.window {
+            if start <= end {
+                if time < start || time >= end {
+                    return false;
+                }
+            } else {
+                // Overnight window: the early-morning part belongs to the previous day
+                if time >= start {
+                    // Same day as the window start
+                } else if time < end {
+                    day = day.pred();
+                } else {
+                    return false;
+                }
+            }
+        }
+
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+/// Returns the first matching entry, if any
+pub fn active_entry(schedule: &[ScheduleEntry], now: DateTime<Local>) -> Option<&ScheduleEntry> {
+    schedule.iter().find(|entry| entry.matches(now))
+}
+
+/// Parse a day name like "mon", "Monday", or the groups "weekdays"/"weekend"
+pub fn parse_days(s: &str) -> Option<Vec<Weekday>> {
+    use Weekday::*;
+
+    match s.trim().to_lowercase().as_str() {
+        "weekdays" => Some(vec![Mon, Tue, Wed, Thu, Fri]),
+        "weekend" | "weekends" => Some(vec![Sat, Sun]),
+        other => other.parse::<Weekday>().ok().map(|d| vec![d]),
+    }
+}
+
+/// Parse a "HH:MM-HH:MM" time window
+pub fn parse_time_window(s: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = s.split_once('-')?;
+    let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+    Some((start, end))
+}
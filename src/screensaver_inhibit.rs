@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use tokio::sync::Mutex;
+use zbus::{connection, fdo::DBusProxy, interface, message::Header};
+
+use crate::idle_timer::{IdleTimer, InhibitSource, ResetSource};
+use crate::log::{log_debug_message, log_error_message, log_message};
+
+const BUS_NAME: &str = "org.freedesktop.ScreenSaver";
+
+/// An inhibit cookie and who holds it
+struct Inhibit {
+    sender: String,
+    app: String,
+}
+
+#[derive(Default)]
+struct Inhibits {
+    next_cookie: u32,
+    held: HashMap<u32, Inhibit>,
+}
+
+/// `org.freedesktop.ScreenSaver`, served at both paths clients use.
+/// Idle actions are paused while any cookie is held.
+struct ScreenSaver {
+    idle_timer: Arc<Mutex<IdleTimer>>,
+    inhibits: Arc<Mutex<Inhibits>>,
+}
+
+#[interface(name = "org.freedesktop.ScreenSaver")]
+impl ScreenSaver {
+    async fn inhibit(&self, application_name: String, reason: String, #[zbus(header)] header: Header<'_>) -> u32 {
+        let sender = header.sender().map(|s| s.to_string()).unwrap_or_default();
+
+        let mut inhibits = self.inhibits.lock().await;
+        inhibits.next_cookie = inhibits.next_cookie.wrapping_add(1).max(1);
+        let cookie = inhibits.next_cookie;
+        inhibits.held.insert(cookie, Inhibit { sender, app: application_name.clone() });
+
+        log_message(&format!("ScreenSaver inhibit by {} ({}), cookie {}", application_name, reason, cookie));
+        if inhibits.held.len() == 1 {
+            set_inhibited(&self.idle_timer, true).await;
+        }
+        cookie
+    }
+
+    async fn un_inhibit(&self, cookie: u32) {
+        let mut inhibits = self.inhibits.lock().await;
+        if let Some(inhibit) = inhibits.held.remove(&cookie) {
+            log_message(&format!("ScreenSaver inhibit released by {}, cookie {}", inhibit.app, cookie));
+            if inhibits.held.is_empty() {
+                set_inhibited(&self.idle_timer, false).await;
+            }
+        }
+    }
+
+    async fn simulate_user_activity(&self) {
+        self.idle_timer.lock().await.reset(ResetSource::ScreenSaver);
+    }
+}
+
+async fn set_inhibited(idle_timer: &Arc<Mutex<IdleTimer>>, inhibited: bool) {
+    idle_timer.lock().await.set_inhibited(InhibitSource::ScreenSaver, inhibited);
+}
+
+/// Serve `org.freedesktop.ScreenSaver` on the session bus so apps that
+/// inhibit through it (browsers, LibreOffice) pause idle actions
+pub fn spawn_screensaver_inhibit(idle_timer: Arc<Mutex<IdleTimer>>) {
+    tokio::spawn(async move {
+        if let Err(e) = serve(idle_timer).await {
+            log_error_message(&format!("ScreenSaver inhibit service failed: {}", e));
+        }
+    });
+}
+
+async fn serve(idle_timer: Arc<Mutex<IdleTimer>>) -> zbus::Result<()> {
+    let inhibits = Arc::new(Mutex::new(Inhibits::default()));
+    let service = |idle_timer: &Arc<Mutex<IdleTimer>>| ScreenSaver {
+        idle_timer: Arc::clone(idle_timer),
+        inhibits: Arc::clone(&inhibits),
+    };
+
+    let connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at("/org/freedesktop/ScreenSaver", service(&idle_timer))?
+        .serve_at("/ScreenSaver", service(&idle_timer))?
+        .build()
+        .await?;
+    log_message("Serving org.freedesktop.ScreenSaver for idle inhibition");
+
+    // Drop cookies of clients that leave the bus without calling UnInhibit
+    let dbus = DBusProxy::new(&connection).await?;
+    let mut stream = dbus.receive_name_owner_changed().await?;
+
+    while let Some(signal) = stream.next().await {
+        let args = signal.args()?;
+        if args.new_owner().is_some() {
+            continue;
+        }
+        let name = args.name().to_string();
+
+        let mut inhibits = inhibits.lock().await;
+        let before = inhibits.held.len();
+        inhibits.held.retain(|_, inhibit| inhibit.sender != name);
+        if inhibits.held.len() < before {
+            log_debug_message(&format!("ScreenSaver: {} left the bus, released its inhibits", name));
+            if inhibits.held.is_empty() {
+                set_inhibited(&idle_timer, false).await;
+            }
+        }
+    }
+
+    Ok(())
+}
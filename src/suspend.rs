@@ -1,47 +1,202 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use futures::StreamExt;
 use tokio::sync::Mutex;
-use zbus::{Connection, fdo::Result as ZbusResult, Proxy};
+use zbus::{Connection, Proxy, fdo::DBusProxy, names::WellKnownName};
+use crate::config::SuspendMethod;
+use crate::error::SuspendError;
 use crate::idle_timer::IdleTimer;
 use crate::log;
 
-pub async fn listen_for_suspend_events(idle_timer: Arc<Mutex<IdleTimer>>) -> ZbusResult<()> {
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How often the clock fallback compares CLOCK_BOOTTIME with CLOCK_MONOTONIC
+const CLOCK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Boottime running ahead of monotonic time by more than this means we slept
+const MIN_SLEEP_GAP: Duration = Duration::from_secs(10);
+/// How often a held-back `resume_command` checks for activity after waking
+const RESUME_ACTIVITY_POLL: Duration = Duration::from_millis(100);
+
+/// A D-Bus sleep manager emitting `PrepareForSleep(bool)`, tried in order
+struct SleepBackend {
+    label: &'static str,
+    service: &'static str,
+    path: &'static str,
+    interface: &'static str,
+}
+
+const BACKENDS: [SleepBackend; 2] = [
+    SleepBackend {
+        label: "logind",
+        service: "org.freedesktop.login1",
+        path: "/org/freedesktop/login1",
+        interface: "org.freedesktop.login1.Manager",
+    },
+    SleepBackend {
+        label: "ConsoleKit",
+        service: "org.freedesktop.ConsoleKit",
+        path: "/org/freedesktop/ConsoleKit/Manager",
+        interface: "org.freedesktop.ConsoleKit.Manager",
+    },
+];
+
+/// Put the machine to sleep through org.freedesktop.login1.Manager, for
+/// `idle.suspend_method`. `interactive` lets polkit ask for authorization.
+pub async fn logind_sleep(method: SuspendMethod, interactive: bool) -> Result<(), SuspendError> {
+    let Some(name) = method.logind_method() else {
+        return Ok(());
+    };
+    let logind = &BACKENDS[0];
+
+    let connection = Connection::system().await?;
+    let proxy = Proxy::new(&connection, logind.service, logind.path, logind.interface).await?;
+    log::log_message(&format!("Calling logind {}", name));
+    proxy.call_method(name, &(interactive,)).await?;
+    Ok(())
+}
+
+/// Listen for logind (or ConsoleKit) sleep signals, reconnecting with backoff
+/// whenever the bus connection fails or the signal stream ends (e.g.
+/// dbus-broker restart). Without either service, resumes are detected from
+/// clock jumps instead, which can't see a suspend coming.
+pub async fn listen_for_suspend_events(idle_timer: Arc<Mutex<IdleTimer>>) {
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+        match listen_once(&idle_timer).await {
+            Ok(()) => log::log_error_message("D-Bus suspend signal stream ended"),
+            Err(SuspendError::NoBackend) => {
+                log::log_message("No logind or ConsoleKit on the system bus, using the clock-jump suspend backend");
+                watch_clock_jumps(&idle_timer).await;
+            }
+            Err(e) => log::log_error_message(&format!("D-Bus suspend event listener failed: {}", e)),
+        }
+
+        // A listener that ran for a while was healthy, start over with a short delay
+        if started.elapsed() > MAX_BACKOFF {
+            backoff = INITIAL_BACKOFF;
+        }
+
+        // Still catch resumes while the bus is unreachable
+        log::log_message(&format!("Reconnecting to D-Bus suspend events in {}s", backoff.as_secs()));
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = watch_clock_jumps(&idle_timer) => {}
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn listen_once(idle_timer: &Arc<Mutex<IdleTimer>>) -> Result<(), SuspendError> {
     // Connect to the system bus
     let connection = Connection::system().await?;
-    
-    // Create proxy (v5+)
-    let proxy = Proxy::new(
-        &connection,
-        "org.freedesktop.login1",        // destination
-        "/org/freedesktop/login1",       // path
-        "org.freedesktop.login1.Manager" // interface
-    ).await?;
-    
+
+    // Use the first sleep manager that is actually running
+    let dbus = DBusProxy::new(&connection).await?;
+    let mut backend = None;
+    for candidate in &BACKENDS {
+        if dbus.name_has_owner(WellKnownName::from_static_str_unchecked(candidate.service).into()).await? {
+            backend = Some(candidate);
+            break;
+        }
+    }
+    let backend = backend.ok_or(SuspendError::NoBackend)?;
+
+    let proxy = Proxy::new(&connection, backend.service, backend.path, backend.interface).await?;
+
     // Listen to PrepareForSleep signals
     let mut stream = proxy.receive_signal("PrepareForSleep").await?;
-    
-    log::log_message("Listening for D-Bus suspend events...");
-    
+
+    log::log_message(&format!("Listening for suspend events via {}", backend.label));
+
     while let Some(signal) = stream.next().await {
         // Deserialize the body directly to bool
         let going_to_sleep: bool = signal.body().deserialize()
             .unwrap_or(false);
-        
-        let mut timer = idle_timer.lock().await;
-        
+
         if going_to_sleep {
             log::log_message("System is preparing to suspend...");
-            timer.trigger_pre_suspend(false, true).await;
+            idle_timer.lock().await.trigger_pre_suspend(false, true).await;
         } else {
             log::log_message("System resumed from sleep");
-            if let Some(cmd) = &timer.resume_command {
-                let cmd_clone = cmd.clone();
-                timer.spawn_task_limited(async move {
-                    let _ = crate::actions::run_command_silent(&cmd_clone).await;
-                });
-            }
+            handle_resume(idle_timer).await;
         }
     }
-    
+
     Ok(())
 }
+
+/// Reset the suspend guard after waking up and run `resume_command`, right
+/// away or once `resume_delay_ms`/`resume_wait_for_activity` allow
+async fn handle_resume(idle_timer: &Arc<Mutex<IdleTimer>>) {
+    let mut timer = idle_timer.lock().await;
+    timer.mark_resumed();
+    let Some(cmd) = timer.resume_command.clone() else {
+        return;
+    };
+
+    let delay = Duration::from_millis(timer.cfg.resume_delay_ms);
+    let wait_for_activity = timer.cfg.resume_wait_for_activity;
+    let woke_at = Instant::now();
+    let idle_timer = Arc::clone(idle_timer);
+    timer.spawn_task_limited(async move {
+        if wait_for_activity {
+            wait_for_activity_since(&idle_timer, woke_at, delay).await;
+        } else if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        if let Err(e) = crate::actions::run_command_silent(&cmd).await {
+            log::log_error_message(&format!("Resume command failed: {}", e));
+        }
+    });
+}
+
+/// Wait until input is seen after `since`, giving up after `ceiling` unless it is zero
+async fn wait_for_activity_since(idle_timer: &Arc<Mutex<IdleTimer>>, since: Instant, ceiling: Duration) {
+    loop {
+        if idle_timer.lock().await.last_activity > since {
+            log::log_debug_message("Activity after wake, running resume command");
+            return;
+        }
+        if !ceiling.is_zero() && since.elapsed() >= ceiling {
+            log::log_debug_message("No activity after wake, running resume command anyway");
+            return;
+        }
+        tokio::time::sleep(RESUME_ACTIVITY_POLL).await;
+    }
+}
+
+/// Detect resumes by CLOCK_BOOTTIME, which keeps counting during sleep,
+/// pulling ahead of CLOCK_MONOTONIC, which does not. Never returns, and just
+/// waits when `/sys/power/state` says the kernel can't sleep at all.
+async fn watch_clock_jumps(idle_timer: &Arc<Mutex<IdleTimer>>) {
+    let states = std::fs::read_to_string("/sys/power/state").unwrap_or_default();
+    if states.trim().is_empty() {
+        log::log_debug_message("No sleep states in /sys/power/state, not watching for resumes");
+        return std::future::pending().await;
+    }
+
+    let mut last = (clock(libc::CLOCK_BOOTTIME), clock(libc::CLOCK_MONOTONIC));
+    loop {
+        tokio::time::sleep(CLOCK_POLL_INTERVAL).await;
+        let now = (clock(libc::CLOCK_BOOTTIME), clock(libc::CLOCK_MONOTONIC));
+        let slept = now.0.saturating_sub(last.0).saturating_sub(now.1.saturating_sub(last.1));
+        last = now;
+
+        if slept > MIN_SLEEP_GAP {
+            log::log_message(&format!("System resumed from sleep (clock jumped {}s)", slept.as_secs()));
+            handle_resume(idle_timer).await;
+        }
+    }
+}
+
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe {
+        libc::clock_gettime(id, &mut ts);
+    }
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
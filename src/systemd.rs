@@ -0,0 +1,36 @@
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+use crate::log::{log_debug_message, log_error_message};
+
+/// Send a state such as `READY=1` to the service manager over `NOTIFY_SOCKET`.
+/// Does nothing when not started by a `Type=notify` unit.
+fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let send = || -> std::io::Result<usize> {
+        let socket = UnixDatagram::unbound()?;
+        // A leading '@' names a socket in the abstract namespace
+        match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => socket.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?),
+            None => socket.send_to(state.as_bytes(), &path),
+        }
+    };
+
+    match send() {
+        Ok(_) => log_debug_message(&format!("Sent {} to systemd", state)),
+        Err(e) => log_error_message(&format!("Failed to notify systemd ({}): {}", state, e)),
+    }
+}
+
+/// Wayland is set up and the control socket is listening
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+pub fn notify_stopping() {
+    notify("STOPPING=1");
+}
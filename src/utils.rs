@@ -1,19 +1,73 @@
 use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// What kind of machine this is, deciding whether AC/battery logic applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chassis {
+    Laptop,
+    Desktop,
+    /// Neither DMI nor a battery gave an answer (e.g. a VM), treated as a desktop
+    Unknown,
+}
+
+impl Chassis {
+    pub fn is_laptop(self) -> bool {
+        self == Chassis::Laptop
+    }
+}
+
+/// `idle.force_laptop` / `idle.force_desktop`, Some(true) for laptop
+static CHASSIS_OVERRIDE: Mutex<Option<bool>> = Mutex::new(None);
+
+pub fn set_chassis_override(laptop: Option<bool>) {
+    *CHASSIS_OVERRIDE.lock().unwrap() = laptop;
+}
+
+/// The configured chassis override, otherwise what sysfs suggests
+pub fn chassis() -> Chassis {
+    match *CHASSIS_OVERRIDE.lock().unwrap() {
+        Some(true) => Chassis::Laptop,
+        Some(false) => Chassis::Desktop,
+        None => detect_chassis(Path::new("/sys")),
+    }
+}
 
 /// Returns true if the system is likely a laptop/notebook/portable
 pub fn is_laptop() -> bool {
-    let chassis_path = "/sys/class/dmi/id/chassis_type";
+    chassis().is_laptop()
+}
 
-    if let Ok(content) = fs::read_to_string(chassis_path) {
-        match content.trim() {
-            "8" | "9" | "10" => true, // 8=Portable, 9=Notebook, 10=Handheld
-            _ => false,
-        }
-    } else {
-        false // Could not read file, assume not a laptop
+/// DMI chassis type first, then whether any battery is present, read from
+/// the sysfs tree at `sys`
+fn detect_chassis(sys: &Path) -> Chassis {
+    let dmi = fs::read_to_string(sys.join("class/dmi/id/chassis_type")).ok();
+    match dmi.as_deref().map(str::trim) {
+        // Portable, Laptop, Notebook, Hand Held, Sub Notebook, Tablet, Convertible, Detachable
+        Some("8" | "9" | "10" | "11" | "14" | "30" | "31" | "32") => Chassis::Laptop,
+        _ if has_battery(sys) => Chassis::Laptop,
+        // Desktop, Low Profile Desktop, Pizza Box, Mini Tower, Tower, All in One,
+        // Main Server Chassis, Rack Mount, Mini PC, Stick PC
+        Some("3" | "4" | "5" | "6" | "7" | "13" | "17" | "23" | "35" | "36") => Chassis::Desktop,
+        _ => Chassis::Unknown,
     }
 }
 
+/// Whether `class/power_supply` lists a system battery
+fn has_battery(sys: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(sys.join("class/power_supply")) else {
+        return false;
+    };
+    entries.filter_map(|e| e.ok()).any(|entry| {
+        let path = entry.path();
+        let is_battery = fs::read_to_string(path.join("type")).is_ok_and(|t| t.trim() == "Battery");
+        // Mice and headsets report their batteries too, scope "Device" marks those
+        let is_peripheral = fs::read_to_string(path.join("scope")).is_ok_and(|s| s.trim() == "Device");
+        is_battery && !is_peripheral
+    })
+}
+
 pub fn format_duration(dur: std::time::Duration) -> String {
     let secs = dur.as_secs();
 
@@ -30,3 +84,114 @@ pub fn format_duration(dur: std::time::Duration) -> String {
     }
 }
 
+/// Parse a duration like "90", "90s", "5m" or "1h", bare numbers are seconds
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let value: u64 = number.parse().map_err(|_| format!("invalid duration '{}'", s))?;
+    let secs = match unit {
+        "" | "s" => value,
+        "m" => value * 60,
+        "h" => value * 3600,
+        _ => return Err(format!("invalid duration unit '{}', expected s, m or h", unit)),
+    };
+    Ok(std::time::Duration::from_secs(secs))
+}
+
+/// Returns true if `program` is an executable path or is found in `PATH`
+pub fn executable_exists(program: &str) -> bool {
+    let is_executable = |path: &Path| {
+        fs::metadata(path)
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| is_executable(&dir.join(program))))
+        .unwrap_or(false)
+}
+
+/// First word of a shell command if it names a program we can look up,
+/// or None when the command uses shell syntax or starts with a builtin
+pub fn command_program(cmd: &str) -> Option<&str> {
+    const SHELL_CHARS: &[char] = &['|', '&', ';', '<', '>', '(', ')', '$', '`'];
+    const BUILTINS: &[&str] = &["exec", "cd", "export", "source", ".", "eval", "test", "[", ":", "true", "false"];
+
+    if cmd.contains(SHELL_CHARS) {
+        return None;
+    }
+
+    let first = cmd.split_whitespace().next()?;
+    if first.contains(['=', '\'', '"', '\\']) || BUILTINS.contains(&first) {
+        return None;
+    }
+    Some(first)
+}
+
+/// Instance id for a `WAYLAND_DISPLAY` value, which compositors set either to a
+/// socket name (`wayland-1`) or an absolute socket path; both yield the basename
+pub fn wayland_instance(display: &str) -> Option<String> {
+    let name = Path::new(display.trim()).file_name()?.to_string_lossy().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Build a throwaway sysfs tree from `(path, contents)` pairs
+    pub(crate) fn sysfs(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("stasis-sysfs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn chassis_from_dmi_then_battery() {
+        let laptop = sysfs("dmi-laptop", &[("class/dmi/id/chassis_type", "10\n")]);
+        assert_eq!(detect_chassis(&laptop), Chassis::Laptop);
+
+        let desktop = sysfs("dmi-desktop", &[("class/dmi/id/chassis_type", "3\n")]);
+        assert_eq!(detect_chassis(&desktop), Chassis::Desktop);
+
+        // A VM with an unhelpful chassis type but a real battery
+        let battery = sysfs(
+            "battery",
+            &[("class/dmi/id/chassis_type", "1\n"), ("class/power_supply/BAT0/type", "Battery\n")],
+        );
+        assert_eq!(detect_chassis(&battery), Chassis::Laptop);
+
+        // A wireless mouse battery doesn't make a laptop
+        let mouse = sysfs(
+            "mouse",
+            &[
+                ("class/dmi/id/chassis_type", "1\n"),
+                ("class/power_supply/hidpp_battery_0/type", "Battery\n"),
+                ("class/power_supply/hidpp_battery_0/scope", "Device\n"),
+            ],
+        );
+        assert_eq!(detect_chassis(&mouse), Chassis::Unknown);
+
+        for root in [laptop, desktop, battery, mouse] {
+            let _ = fs::remove_dir_all(root);
+        }
+    }
+
+    #[test]
+    fn bare_and_absolute_wayland_display_share_an_instance() {
+        assert_eq!(wayland_instance("wayland-1").as_deref(), Some("wayland-1"));
+        assert_eq!(wayland_instance("/run/user/1000/wayland-1").as_deref(), Some("wayland-1"));
+        assert_eq!(wayland_instance(" wayland-1\n").as_deref(), Some("wayland-1"));
+        assert_eq!(wayland_instance(""), None);
+    }
+}
@@ -2,8 +2,8 @@ use eyre::Result;
 use std::sync::Arc;
 use std::time::Duration;
 
-use crate::idle_timer::IdleTimer;
-use crate::log::{log_error_message, log_message};
+use crate::idle_timer::{IdleTimer, ResetSource};
+use crate::log::{log_debug_message, log_error_message, log_message, log_trace_message};
 
 use tokio::sync::Notify;
 use tokio::time::sleep;
@@ -21,16 +21,51 @@ use wayland_protocols::wp::idle_inhibit::zv1::client::{
     zwp_idle_inhibitor_v1::{ZwpIdleInhibitorV1, Event as InhibitorEvent},
 };
 
+pub mod toplevels;
+use toplevels::ToplevelApps;
+use wayland_protocols_wlr::foreign_toplevel::v1::client::zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1;
+
+#[cfg(feature = "wlr_output_power")]
+pub mod output_power;
+#[cfg(feature = "wlr_output_power")]
+use wayland_client::protocol::wl_output::WlOutput;
+#[cfg(feature = "wlr_output_power")]
+use wayland_protocols_wlr::output_power_management::v1::client::zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1;
+
+#[cfg(feature = "wlroots_virtual_keyboard")]
+pub mod virtual_keyboard;
+#[cfg(feature = "wlroots_virtual_keyboard")]
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::zwp_virtual_keyboard_manager_v1::ZwpVirtualKeyboardManagerV1;
+
+/// A bound seat and its name, once the compositor has sent it
+pub struct Seat {
+    pub seat: WlSeat,
+    pub name: Option<String>,
+}
+
 /// Holds Wayland idle state and handles integration with IdleTimer
 pub struct WaylandIdleData {
     pub idle_timer: Arc<tokio::sync::Mutex<IdleTimer>>,
     pub idle_notifier: Option<ExtIdleNotifierV1>,
-    pub seat: Option<WlSeat>,
-    pub notification: Option<ExtIdleNotificationV1>,
+    pub seats: Vec<Seat>,
+    /// One idle notification per watched seat, indexed like `seats_idle`
+    pub notifications: Vec<ExtIdleNotificationV1>,
+    pub seats_idle: Vec<bool>,
+    /// Timeout the current idle notifications were created with
+    pub armed_timeout_ms: Option<u32>,
+    qh: Option<QueueHandle<Self>>,
     pub inhibit_manager: Option<ZwpIdleInhibitManagerV1>,
     pub active_inhibitors: u32,
     pub respect_inhibitors: bool,
     pub shutdown: Arc<Notify>,
+    pub toplevel_manager: Option<ZwlrForeignToplevelManagerV1>,
+    pub toplevel_apps: ToplevelApps,
+    #[cfg(feature = "wlr_output_power")]
+    pub output_power_manager: Option<ZwlrOutputPowerManagerV1>,
+    #[cfg(feature = "wlr_output_power")]
+    pub outputs: Arc<std::sync::Mutex<Vec<WlOutput>>>,
+    #[cfg(feature = "wlroots_virtual_keyboard")]
+    pub virtual_keyboard_manager: Option<ZwpVirtualKeyboardManagerV1>,
 }
 
 impl WaylandIdleData {
@@ -38,18 +73,64 @@ impl WaylandIdleData {
         Self {
             idle_timer,
             idle_notifier: None,
-            seat: None,
-            notification: None,
+            seats: Vec::new(),
+            notifications: Vec::new(),
+            seats_idle: Vec::new(),
+            armed_timeout_ms: None,
+            qh: None,
             inhibit_manager: None,
             active_inhibitors: 0,
             respect_inhibitors,
             shutdown: Arc::new(Notify::new()),
+            toplevel_manager: None,
+            toplevel_apps: ToplevelApps::default(),
+            #[cfg(feature = "wlr_output_power")]
+            output_power_manager: None,
+            #[cfg(feature = "wlr_output_power")]
+            outputs: Arc::new(std::sync::Mutex::new(Vec::new())),
+            #[cfg(feature = "wlroots_virtual_keyboard")]
+            virtual_keyboard_manager: None,
         }
     }
 
     pub fn is_inhibited(&self) -> bool {
         self.respect_inhibitors && self.active_inhibitors > 0
     }
+
+    /// Create one idle notification per watched seat. Returns false without
+    /// an idle notifier or seats.
+    fn arm_notifications(&mut self, timeout_ms: u32) -> bool {
+        let (Some(notifier), Some(qh)) = (&self.idle_notifier, &self.qh) else {
+            return false;
+        };
+        if self.seats.is_empty() {
+            return false;
+        }
+
+        self.notifications = self
+            .seats
+            .iter()
+            .enumerate()
+            .map(|(i, seat)| notifier.get_idle_notification(timeout_ms, &seat.seat, qh, i))
+            .collect();
+        self.seats_idle = vec![false; self.notifications.len()];
+        self.armed_timeout_ms = Some(timeout_ms);
+        true
+    }
+
+    /// Recreate the idle notifications when the timeout changed, e.g. on a
+    /// power switch or reload; the event loop flushes the requests on its next pass
+    pub fn rearm_notifications(&mut self, timeout_ms: u32) {
+        if self.armed_timeout_ms.is_none_or(|armed| armed == timeout_ms) {
+            return;
+        }
+        for notification in self.notifications.drain(..) {
+            notification.destroy();
+        }
+        if self.arm_notifications(timeout_ms) {
+            log_message(&format!("Compositor idle timeout re-armed at {}ms", timeout_ms));
+        }
+    }
 }
 
 /// Bind registry globals
@@ -62,21 +143,47 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandIdleData {
         _: &Connection,
         qh: &QueueHandle<Self>,
     ) {
-        if let wl_registry::Event::Global { name, interface, .. } = event {
+        if let wl_registry::Event::Global { name, interface, version } = event {
             match interface.as_str() {
                 "ext_idle_notifier_v1" => {
                     state.idle_notifier =
                         Some(registry.bind::<ExtIdleNotifierV1, _, _>(name, 1, qh, ()));
-                    log_message("Binding ext_idle_notifier_v1");
+                    log_debug_message("Binding ext_idle_notifier_v1");
                 }
                 "wl_seat" => {
-                    state.seat = Some(registry.bind::<WlSeat, _, _>(name, 1, qh, ()));
-                    log_message("Binding wl_seat");
+                    // Version 2 adds the name event used by `idle.seat`
+                    let seat = registry.bind::<WlSeat, _, _>(name, version.min(2), qh, ());
+                    state.seats.push(Seat { seat, name: None });
+                    log_debug_message("Binding wl_seat");
                 }
                 "zwp_idle_inhibit_manager_v1" => {
                     state.inhibit_manager =
                         Some(registry.bind::<ZwpIdleInhibitManagerV1, _, _>(name, 1, qh, ()));
-                    log_message("Binding zwp_idle_inhibit_manager_v1");
+                    log_debug_message("Binding zwp_idle_inhibit_manager_v1");
+                }
+                "zwlr_foreign_toplevel_manager_v1" => {
+                    state.toplevel_manager =
+                        Some(registry.bind::<ZwlrForeignToplevelManagerV1, _, _>(name, 1, qh, ()));
+                    log_debug_message("Binding zwlr_foreign_toplevel_manager_v1");
+                }
+                #[cfg(feature = "wlr_output_power")]
+                "zwlr_output_power_manager_v1" => {
+                    state.output_power_manager =
+                        Some(registry.bind::<ZwlrOutputPowerManagerV1, _, _>(name, 1, qh, ()));
+                    log_debug_message("Binding zwlr_output_power_manager_v1");
+                }
+                #[cfg(feature = "wlr_output_power")]
+                "wl_output" => {
+                    let output = registry.bind::<WlOutput, _, _>(name, 1, qh, ());
+                    if let Ok(mut outputs) = state.outputs.lock() {
+                        outputs.push(output);
+                    }
+                }
+                #[cfg(feature = "wlroots_virtual_keyboard")]
+                "zwp_virtual_keyboard_manager_v1" => {
+                    state.virtual_keyboard_manager =
+                        Some(registry.bind::<ZwpVirtualKeyboardManagerV1, _, _>(name, 1, qh, ()));
+                    log_debug_message("Binding zwp_virtual_keyboard_manager_v1");
                 }
                 _ => {}
             }
@@ -95,38 +202,59 @@ impl Dispatch<ExtIdleNotifierV1, ()> for WaylandIdleData {
     ) {}
 }
 
-impl Dispatch<ExtIdleNotificationV1, ()> for WaylandIdleData {
+/// User data is the seat's index in `seats_idle`
+impl Dispatch<ExtIdleNotificationV1, usize> for WaylandIdleData {
     fn event(
         state: &mut Self,
         _: &ExtIdleNotificationV1,
         event: IdleEvent,
-        _: &(),
+        index: &usize,
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
+        log_trace_message(&format!("Idle notification on seat {}: {:?}", index, event));
+
+        // Idle only once every seat is idle, activity on any seat counts
+        match event {
+            IdleEvent::Idled => {
+                if let Some(idle) = state.seats_idle.get_mut(*index) {
+                    *idle = true;
+                }
+                if !state.seats_idle.iter().all(|&idle| idle) {
+                    return;
+                }
+            }
+            IdleEvent::Resumed => {
+                if let Some(idle) = state.seats_idle.get_mut(*index) {
+                    *idle = false;
+                }
+            }
+            _ => {}
+        }
+
         let idle_timer = Arc::clone(&state.idle_timer);
         let inhibited = state.is_inhibited();
 
         tokio::spawn(async move {
             if inhibited {
-                log_message("Idle inhibited by an app; skipping idle trigger");
+                log_debug_message("Idle inhibited by an app; skipping idle trigger");
                 return;
             }
 
             let mut timer = idle_timer.lock().await;
-            if timer.is_compositor_managed() {
+            if timer.is_compositor_managed() || timer.presentation {
                 return;
             }
 
             match event {
                 IdleEvent::Idled => {
-                    log_message("Compositor detected idle state");
+                    log_debug_message("Compositor detected idle state");
                     timer.mark_all_idle();
                     timer.trigger_idle().await;
                 }
                 IdleEvent::Resumed => {
-                    log_message("Compositor detected activity");
-                    timer.reset();
+                    log_debug_message("Compositor detected activity");
+                    timer.reset(ResetSource::Compositor);
                 }
                 _ => {}
             }
@@ -144,7 +272,7 @@ impl Dispatch<ZwpIdleInhibitorV1, ()> for WaylandIdleData {
         _: &QueueHandle<Self>,
     ) {
         state.active_inhibitors += 1;
-        log_message(&format!("Inhibitor created, count={}", state.active_inhibitors));
+        log_trace_message(&format!("Inhibitor created, count={}", state.active_inhibitors));
     }
 }
 
@@ -159,20 +287,27 @@ impl Dispatch<ZwpIdleInhibitManagerV1, ()> for WaylandIdleData {
     ) {
         if state.active_inhibitors > 0 {
             state.active_inhibitors -= 1;
-            log_message(&format!("Inhibitor removed, count={}", state.active_inhibitors));
+            log_trace_message(&format!("Inhibitor removed, count={}", state.active_inhibitors));
         }
     }
 }
 
 impl Dispatch<WlSeat, ()> for WaylandIdleData {
     fn event(
-        _: &mut Self,
-        _: &WlSeat,
-        _: wayland_client::protocol::wl_seat::Event,
+        state: &mut Self,
+        proxy: &WlSeat,
+        event: wayland_client::protocol::wl_seat::Event,
         _: &(),
         _: &Connection,
         _: &QueueHandle<Self>,
-    ) {}
+    ) {
+        if let wayland_client::protocol::wl_seat::Event::Name { name } = event
+            && let Some(seat) = state.seats.iter_mut().find(|s| &s.seat == proxy)
+        {
+            log_message(&format!("Found seat '{}'", name));
+            seat.name = Some(name);
+        }
+    }
 }
 
 /// Setup Wayland idle detection
@@ -189,22 +324,68 @@ pub async fn setup(
     let display = conn.display();
 
     let mut app_data = WaylandIdleData::new(idle_timer.clone(), respect_inhibitors);
+    app_data.qh = Some(qh.clone());
     let _registry = display.get_registry(&qh, ());
     event_queue.roundtrip(&mut app_data)?;
+    // Second roundtrip delivers the names of the seats bound above
+    event_queue.roundtrip(&mut app_data)?;
 
-    if let (Some(notifier), Some(seat)) = (&app_data.idle_notifier, &app_data.seat) {
-        let timeout_ms = {
-            let timer = idle_timer.lock().await;
-            timer.shortest_timeout().as_millis() as u32
-        };
-        let notification = notifier.get_idle_notification(timeout_ms, seat, &qh, ());
-        app_data.notification = Some(notification);
+    let wanted_seat = idle_timer.lock().await.cfg.seat.clone();
+    if let Some(wanted) = &wanted_seat {
+        if app_data.seats.iter().any(|s| s.name.as_deref() == Some(wanted)) {
+            app_data.seats.retain(|s| s.name.as_deref() == Some(wanted));
+        } else {
+            log_error_message(&format!("Seat '{}' not found, watching all seats", wanted));
+        }
+    }
+
+    // Subscribed under the same lock, so no change slips in between
+    let (timeout_ms, mut timeout_changes) = {
+        let timer = idle_timer.lock().await;
+        (timer.compositor_timeout_ms(), timer.subscribe_compositor_timeout())
+    };
+    if app_data.arm_notifications(timeout_ms) {
+        log_message(&format!("Watching idle on {} seat(s)", app_data.notifications.len()));
 
         let mut timer = idle_timer.lock().await;
         timer.set_compositor_managed(true);
         log_message("Wayland idle detection active");
     }
 
+    #[cfg(feature = "wlr_output_power")]
+    if let Some(manager) = &app_data.output_power_manager {
+        let handle = output_power::OutputPower::new(
+            conn.clone(),
+            qh.clone(),
+            manager.clone(),
+            Arc::clone(&app_data.outputs),
+        );
+        idle_timer.lock().await.set_output_power(handle);
+        log_message("Native DPMS available via wlr-output-power-management");
+    }
+
+    #[cfg(feature = "wlroots_virtual_keyboard")]
+    let (keep_alive, wake_key) = {
+        let timer = idle_timer.lock().await;
+        (timer.cfg.keep_alive_seconds > 0, timer.cfg.wake_key.clone())
+    };
+    #[cfg(feature = "wlroots_virtual_keyboard")]
+    if keep_alive || wake_key.is_some() {
+        match (&app_data.virtual_keyboard_manager, app_data.seats.first()) {
+            (Some(manager), Some(seat)) => {
+                let keyboard = manager.create_virtual_keyboard(&seat.seat, &qh, ());
+                match virtual_keyboard::VirtualKeyboard::new(conn.clone(), keyboard, wake_key) {
+                    Ok(handle) => {
+                        idle_timer.lock().await.set_virtual_keyboard(handle);
+                        log_message("Keep-alive and wake key available via zwp-virtual-keyboard");
+                    }
+                    Err(e) => log_error_message(&format!("Failed to set up virtual keyboard: {}", e)),
+                }
+            }
+            _ => log_error_message("keep_alive_seconds or wake_key is set but the compositor lacks zwp-virtual-keyboard"),
+        }
+    }
+
     let app_data = Arc::new(tokio::sync::Mutex::new(app_data));
     let shutdown = {
         let locked = app_data.lock().await;
@@ -218,6 +399,11 @@ pub async fn setup(
             loop {
                 {
                     let mut locked_data = app_data.lock().await;
+                    // Pull in new events without blocking; WouldBlock just means none arrived
+                    let _ = event_queue.flush();
+                    if let Some(guard) = event_queue.prepare_read() {
+                        let _ = guard.read();
+                    }
                     if let Err(e) = event_queue.dispatch_pending(&mut *locked_data) {
                         log_error_message(&format!("Wayland event error: {}", e));
                     }
@@ -226,8 +412,18 @@ pub async fn setup(
                 tokio::select! {
                     _ = shutdown.notified() => {
                         log_message("Wayland event loop shutting down");
+                        let mut locked_data = app_data.lock().await;
+                        for notification in locked_data.notifications.drain(..) {
+                            notification.destroy();
+                        }
+                        locked_data.seats_idle.clear();
+                        let _ = event_queue.flush();
                         break;
                     }
+                    Ok(()) = timeout_changes.changed() => {
+                        let timeout_ms = *timeout_changes.borrow_and_update();
+                        app_data.lock().await.rearm_notifications(timeout_ms);
+                    }
                     _ = sleep(Duration::from_millis(50)) => {}
                 }
             }
@@ -0,0 +1,95 @@
+use std::sync::{Arc, Mutex};
+
+use wayland_client::{
+    protocol::wl_output::{self, WlOutput},
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols_wlr::output_power_management::v1::client::{
+    zwlr_output_power_manager_v1::{self, ZwlrOutputPowerManagerV1},
+    zwlr_output_power_v1::{self, ZwlrOutputPowerV1},
+};
+
+pub use wayland_protocols_wlr::output_power_management::v1::client::zwlr_output_power_v1::Mode as PowerMode;
+
+use super::WaylandIdleData;
+use crate::log::{log_error_message, log_message};
+
+/// Handle for switching outputs on/off through wlr-output-power-management,
+/// cloned out of the Wayland state so IdleTimer can use it directly
+#[derive(Clone)]
+pub struct OutputPower {
+    conn: Connection,
+    qh: QueueHandle<WaylandIdleData>,
+    manager: ZwlrOutputPowerManagerV1,
+    outputs: Arc<Mutex<Vec<WlOutput>>>,
+}
+
+impl OutputPower {
+    pub fn new(
+        conn: Connection,
+        qh: QueueHandle<WaylandIdleData>,
+        manager: ZwlrOutputPowerManagerV1,
+        outputs: Arc<Mutex<Vec<WlOutput>>>,
+    ) -> Self {
+        Self { conn, qh, manager, outputs }
+    }
+
+    /// Set the power mode of every known output
+    pub fn set_all_outputs(&self, mode: PowerMode) {
+        let outputs = match self.outputs.lock() {
+            Ok(outputs) => outputs.clone(),
+            Err(_) => return,
+        };
+
+        for output in &outputs {
+            let power = self.manager.get_output_power(output, &self.qh, ());
+            power.set_mode(mode);
+            // The mode stays applied after the control object is gone
+            power.destroy();
+        }
+
+        if let Err(e) = self.conn.flush() {
+            log_error_message(&format!("Failed to flush output power requests: {}", e));
+            return;
+        }
+
+        log_message(&format!("Set {} outputs to {:?} via wlr-output-power-management", outputs.len(), mode));
+    }
+}
+
+impl Dispatch<ZwlrOutputPowerManagerV1, ()> for WaylandIdleData {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrOutputPowerManagerV1,
+        _: zwlr_output_power_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {}
+}
+
+impl Dispatch<ZwlrOutputPowerV1, ()> for WaylandIdleData {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrOutputPowerV1,
+        event: zwlr_output_power_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_output_power_v1::Event::Failed = event {
+            log_error_message("Compositor rejected output power mode change");
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for WaylandIdleData {
+    fn event(
+        _: &mut Self,
+        _: &WlOutput,
+        _: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {}
+}
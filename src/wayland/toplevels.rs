@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use wayland_client::{
+    backend::ObjectId,
+    event_created_child, Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use super::WaylandIdleData;
+use crate::log::log_message;
+
+/// `zwlr_foreign_toplevel_handle_v1.state` value for fullscreen
+const STATE_FULLSCREEN: u32 = 3;
+
+#[derive(Default)]
+struct Toplevel {
+    app_id: String,
+    fullscreen: bool,
+}
+
+/// Every open toplevel, keyed by its handle, as reported by
+/// wlr-foreign-toplevel-management. Shared with the AppInhibitor.
+#[derive(Clone, Default)]
+pub struct ToplevelApps(Arc<Mutex<HashMap<ObjectId, Toplevel>>>);
+
+impl ToplevelApps {
+    /// Currently open windows as (app_id, fullscreen)
+    pub fn windows(&self) -> Vec<(String, bool)> {
+        match self.0.lock() {
+            Ok(apps) => apps.values().map(|t| (t.app_id.clone(), t.fullscreen)).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn update(&self, handle: ObjectId, f: impl FnOnce(&mut Toplevel)) {
+        if let Ok(mut apps) = self.0.lock() {
+            f(apps.entry(handle).or_default());
+        }
+    }
+
+    fn remove(&self, handle: &ObjectId) {
+        if let Ok(mut apps) = self.0.lock() {
+            apps.remove(handle);
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for WaylandIdleData {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.toplevel_apps.update(toplevel.id(), |_| {});
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                log_message("Foreign toplevel manager finished");
+                state.toplevel_manager = None;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(WaylandIdleData, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for WaylandIdleData {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                state.toplevel_apps.update(handle.id(), |t| t.app_id = app_id);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: states } => {
+                // Array of native-endian u32 state values
+                let fullscreen = states
+                    .chunks_exact(4)
+                    .any(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]) == STATE_FULLSCREEN);
+                state.toplevel_apps.update(handle.id(), |t| t.fullscreen = fullscreen);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                state.toplevel_apps.remove(&handle.id());
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+}
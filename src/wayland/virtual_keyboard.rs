@@ -0,0 +1,151 @@
+use std::ffi::CString;
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols_misc::zwp_virtual_keyboard_v1::client::{
+    zwp_virtual_keyboard_manager_v1::{self, ZwpVirtualKeyboardManagerV1},
+    zwp_virtual_keyboard_v1::{self, ZwpVirtualKeyboardV1},
+};
+
+use super::WaylandIdleData;
+use crate::config::WakeKey;
+use crate::log::{log_debug_message, log_error_message};
+
+/// wl_keyboard keymap format XKB_V1
+const KEYMAP_FORMAT_XKB_V1: u32 = 1;
+/// evdev KEY_F24, unbound almost everywhere so pressing it is harmless
+const KEY_F24: u32 = 194;
+const KEY_RELEASED: u32 = 0;
+const KEY_PRESSED: u32 = 1;
+
+/// Keymap with F24 and the wake key, xkb keycodes are evdev codes offset by 8
+fn keymap(wake_key: Option<&WakeKey>) -> String {
+    let mut keycodes = format!("<I{0}> = {0};", KEY_F24 + 8);
+    let mut symbols = format!("key <I{}> {{ [ F24 ] }};", KEY_F24 + 8);
+    if let Some(key) = wake_key.filter(|k| k.code != KEY_F24) {
+        keycodes.push_str(&format!(" <I{0}> = {0};", key.code + 8));
+        symbols.push_str(&format!(" key <I{}> {{ [ {} ] }};", key.code + 8, key.keysym));
+    }
+
+    format!(
+        "xkb_keymap {{
+  xkb_keycodes \"stasis\" {{ minimum = 8; maximum = 255; {} }};
+  xkb_types \"stasis\" {{ include \"complete\" }};
+  xkb_compatibility \"stasis\" {{ include \"complete\" }};
+  xkb_symbols \"stasis\" {{ {} }};
+}};
+",
+        keycodes, symbols
+    )
+}
+
+/// Handle for sending synthetic key presses through zwp-virtual-keyboard,
+/// used to keep other idle systems awake while Stasis is inhibited and to
+/// wake displays with `wake_key` when Stasis ends idle on its own
+#[derive(Clone)]
+pub struct VirtualKeyboard {
+    conn: Connection,
+    keyboard: ZwpVirtualKeyboardV1,
+    wake_key: Option<WakeKey>,
+}
+
+impl VirtualKeyboard {
+    /// Upload the keymap, which the protocol requires before any key event
+    pub fn new(conn: Connection, keyboard: ZwpVirtualKeyboardV1, wake_key: Option<WakeKey>) -> std::io::Result<Self> {
+        let keymap = keymap(wake_key.as_ref());
+        let fd = keymap_fd(&keymap)?;
+        keyboard.keymap(KEYMAP_FORMAT_XKB_V1, fd.as_fd(), keymap_size(&keymap));
+        conn.flush().map_err(std::io::Error::other)?;
+        Ok(Self { conn, keyboard, wake_key })
+    }
+
+    /// Press and release F24
+    pub fn send_keep_alive(&self) {
+        if self.send_key(KEY_F24) {
+            log_debug_message("Sent keep-alive key via virtual keyboard");
+        }
+    }
+
+    /// Press and release `wake_key`, if one is configured
+    pub fn send_wake_key(&self) {
+        let Some(key) = &self.wake_key else {
+            return;
+        };
+        if self.send_key(key.code) {
+            log_debug_message(&format!("Sent wake key {} via virtual keyboard", key));
+        }
+    }
+
+    fn send_key(&self, keycode: u32) -> bool {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u32)
+            .unwrap_or(0);
+
+        self.keyboard.key(time, keycode, KEY_PRESSED);
+        self.keyboard.key(time.wrapping_add(1), keycode, KEY_RELEASED);
+
+        if let Err(e) = self.conn.flush() {
+            log_error_message(&format!("Failed to flush virtual keyboard key: {}", e));
+            return false;
+        }
+        true
+    }
+}
+
+/// Size announced with the keymap, which includes the trailing NUL
+fn keymap_size(keymap: &str) -> u32 {
+    keymap.len() as u32 + 1
+}
+
+/// Write the keymap into a memfd the compositor can map
+fn keymap_fd(keymap: &str) -> std::io::Result<OwnedFd> {
+    let name = CString::new("stasis-keymap").expect("static name has no NUL");
+    let raw = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if raw < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+    let mut file = std::fs::File::from(fd);
+    std::io::Write::write_all(&mut file, keymap.as_bytes())?;
+    std::io::Write::write_all(&mut file, &[0])?;
+    Ok(OwnedFd::from(file))
+}
+
+impl Dispatch<ZwpVirtualKeyboardManagerV1, ()> for WaylandIdleData {
+    fn event(
+        _: &mut Self,
+        _: &ZwpVirtualKeyboardManagerV1,
+        _: zwp_virtual_keyboard_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {}
+}
+
+impl Dispatch<ZwpVirtualKeyboardV1, ()> for WaylandIdleData {
+    fn event(
+        _: &mut Self,
+        _: &ZwpVirtualKeyboardV1,
+        _: zwp_virtual_keyboard_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymap_memfd_holds_the_keymap_and_its_nul() {
+        let wake_key = WakeKey::parse("shift");
+        let keymap = keymap(wake_key.as_ref());
+        let file = std::fs::File::from(keymap_fd(&keymap).unwrap());
+        assert_eq!(file.metadata().unwrap().len(), keymap.len() as u64 + 1);
+        assert_eq!(keymap_size(&keymap) as u64, keymap.len() as u64 + 1);
+    }
+}